@@ -0,0 +1,38 @@
+//! Decodes arbitrary bytes, renders the instruction in every syntax, and
+//! checks it encodes to bytes that decode back to it.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use r8086::*;
+
+fuzz_target!(|data: &[u8]| {
+    let instruction = decode_instruction(data);
+
+    for syntax in [Syntax::Nasm, Syntax::Masm, Syntax::Intel, Syntax::Att] {
+        for radix in [Radix::Decimal, Radix::Hex] {
+            let options = FormatOptions {
+                syntax,
+                radix,
+                signed: true,
+            };
+            instruction.display(options).to_string();
+        }
+    }
+
+    let encoded = encode_instruction(&instruction).expect("decoded instruction has no encoding");
+    assert!(encoded.len() <= instruction.length as usize);
+    if instruction.op == Op::Db {
+        assert_eq!(encoded, data[..1]);
+    } else {
+        let reencoded = decode_instruction(&encoded);
+        assert_eq!(reencoded.length as usize, encoded.len());
+        assert_eq!(
+            Instruction {
+                length: instruction.length,
+                ..reencoded
+            },
+            instruction
+        );
+    }
+});
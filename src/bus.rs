@@ -0,0 +1,39 @@
+/// Physical addresses wrap at 1 MiB, the reach of the 20 address lines.
+pub(crate) const ADDRESS_MASK: usize = 0xfffff;
+
+/// The memory side of the CPU: everything the simulator reads and writes
+/// goes through here, addressed by 20-bit physical address.
+///
+/// The simulator wraps addresses at 1 MiB before they get here, and splits
+/// a word at offset FFFFh of a segment into two byte accesses since its high
+/// byte comes from offset 0 of the same segment.
+pub trait Bus {
+    fn read8(&mut self, address: usize) -> u8;
+
+    fn write8(&mut self, address: usize, value: u8);
+
+    /// Reads a little-endian word, the high byte from the next address.
+    fn read16(&mut self, address: usize) -> u16 {
+        let lo = self.read8(address);
+        let hi = self.read8((address + 1) & ADDRESS_MASK);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+        self.write8(address, lo);
+        self.write8((address + 1) & ADDRESS_MASK, hi);
+    }
+
+    /// Called with CS:IP before every instruction and interrupt, for buses
+    /// that attribute accesses to the code making them.
+    fn set_instruction(&mut self, _cs: u16, _ip: u16) {}
+}
+
+/// A device answering memory cycles in a mapped address range. Offsets are
+/// relative to the start of the range.
+pub trait MmioDevice {
+    fn read8(&mut self, offset: usize) -> u8;
+
+    fn write8(&mut self, offset: usize, value: u8);
+}
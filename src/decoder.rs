@@ -1,15 +1,62 @@
 use crate::*;
 
+/// Longest instruction without prefixes: opcode, mod-reg-r/m, two bytes of
+/// displacement and two of immediate data.
+pub const MAX_INSTRUCTION_LENGTH: usize = 6;
+
+/// Decodes the instruction at the start of `bytes`. Any input decodes to
+/// something: bytes past the end of the slice read as zero, and a byte that
+/// starts no instruction known here decodes as a one byte [`Op::Db`].
 pub fn decode_instruction(bytes: &[u8]) -> Instruction {
+    let mut prefixes = Prefixes::default();
+    let mut prefix_len = 0;
+    // The length has to fit in a u8; a longer run of prefixes ends as data.
+    while prefix_len < u8::MAX as usize - MAX_INSTRUCTION_LENGTH {
+        match bytes.get(prefix_len) {
+            Some(&byte @ (0x26 | 0x2e | 0x36 | 0x3e)) => {
+                prefixes.segment = Some(decode_segment((byte >> 3) & 0b11))
+            }
+            Some(0xf0) => prefixes.lock = true,
+            Some(0xf2) => prefixes.rep = Some(Rep::Repne),
+            Some(0xf3) => prefixes.rep = Some(Rep::Rep),
+            _ => break,
+        }
+        prefix_len += 1;
+    }
+
+    let mut window = [0; MAX_INSTRUCTION_LENGTH];
+    let rest = bytes.get(prefix_len..).unwrap_or_default();
+    let available = rest.len().min(MAX_INSTRUCTION_LENGTH);
+    window[..available].copy_from_slice(&rest[..available]);
+
+    let mut instruction = decode_opcode(&window);
+    if instruction.op == Op::Db {
+        // Prefixes with nothing to apply to are data themselves.
+        return db(bytes.first().copied().unwrap_or_default());
+    }
+    if let Some(segment) = prefixes.segment {
+        for operand in instruction.operands.iter_mut().flatten() {
+            if let Operand::Memory(mem) = operand {
+                mem.segment = Some(segment);
+            }
+        }
+    }
+    instruction.prefixes = prefixes;
+    instruction.length += prefix_len as u8;
+    instruction
+}
+
+/// Decodes the instruction starting at the opcode in `bytes[0]`.
+fn decode_opcode(bytes: &[u8; MAX_INSTRUCTION_LENGTH]) -> Instruction {
     let bits = (
-        (bytes[0] >> 7) & 1 as u8,
-        (bytes[0] >> 6) & 1 as u8,
-        (bytes[0] >> 5) & 1 as u8,
-        (bytes[0] >> 4) & 1 as u8,
-        (bytes[0] >> 3) & 1 as u8,
-        (bytes[0] >> 2) & 1 as u8,
-        (bytes[0] >> 1) & 1 as u8,
-        (bytes[0] >> 0) & 1 as u8,
+        (bytes[0] >> 7) & 1,
+        (bytes[0] >> 6) & 1,
+        (bytes[0] >> 5) & 1,
+        (bytes[0] >> 4) & 1,
+        (bytes[0] >> 3) & 1,
+        (bytes[0] >> 2) & 1,
+        (bytes[0] >> 1) & 1,
+        bytes[0] & 1,
     );
 
     match bits {
@@ -45,12 +92,12 @@ pub fn decode_instruction(bytes: &[u8]) -> Instruction {
                 _ => unreachable!(),
             };
 
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Mov,
                 operands: [Some(dest), Some(src)],
                 length: len,
-            };
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // MOV | Immediate to register/memory
         (1, 1, 0, 0, 0, 1, 1, w) => {
@@ -102,13 +149,12 @@ pub fn decode_instruction(bytes: &[u8]) -> Instruction {
                 Operand::Immediate(Immediate::Bit16(imm))
             };
 
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Mov,
                 operands: [Some(dest), Some(imm)],
                 length: len + w,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // MOV | Immediate to register
         (1, 0, 1, 1, w, r2, r1, r0) => {
@@ -123,67 +169,44 @@ pub fn decode_instruction(bytes: &[u8]) -> Instruction {
                 Operand::Immediate(Immediate::Bit16(imm))
             };
 
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Mov,
                 operands: [Some(reg0), Some(imm)],
                 length: 2 + w,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // MOV | Memory to accumulator
         (1, 0, 1, 0, 0, 0, 0, w) => {
-            let addr_lo = bytes[1];
-            let addr_hi = bytes[2];
-
-            let mem = if w == 0 {
-                Operand::Memory(MemoryOperand {
-                    kind: MemoryOperandKind::Direct_Address(addr_lo as _),
-                    size: MemoryOperandSize::Byte,
-                })
-            } else {
-                let addr = ((addr_hi as u16) << 8) + addr_lo as u16;
-                Operand::Memory(MemoryOperand {
-                    kind: MemoryOperandKind::Direct_Address(addr),
-                    size: MemoryOperandSize::Word,
-                })
-            };
-
-            let ax = Operand::Register(Register::AX);
-            let instruction = Instruction {
+            let addr = u16::from_le_bytes([bytes[1], bytes[2]]);
+            let mem = Operand::Memory(MemoryOperand {
+                kind: MemoryOperandKind::Direct_Address(addr),
+                size: MemoryOperandSize::from_w_bit(w),
+                segment: None,
+            });
+            let acc = decode_register(0b000, w);
+            Instruction {
                 op: Op::Mov,
-                operands: [Some(ax), Some(mem)],
-                length: 2 + w,
-            };
-
-            instruction
+                operands: [Some(acc), Some(mem)],
+                length: 3,
+                prefixes: Prefixes::default(),
+            }
         }
         // MOV | Accumulator to memory
         (1, 0, 1, 0, 0, 0, 1, w) => {
-            let addr_lo = bytes[1];
-            let addr_hi = bytes[2];
-
-            let mem = if w == 0 {
-                Operand::Memory(MemoryOperand {
-                    kind: MemoryOperandKind::Direct_Address(addr_lo as _),
-                    size: MemoryOperandSize::Byte,
-                })
-            } else {
-                let addr = ((addr_hi as u16) << 8) + addr_lo as u16;
-                Operand::Memory(MemoryOperand {
-                    kind: MemoryOperandKind::Direct_Address(addr),
-                    size: MemoryOperandSize::Word,
-                })
-            };
-
-            let ax = Operand::Register(Register::AX);
-            let instruction = Instruction {
+            let addr = u16::from_le_bytes([bytes[1], bytes[2]]);
+            let mem = Operand::Memory(MemoryOperand {
+                kind: MemoryOperandKind::Direct_Address(addr),
+                size: MemoryOperandSize::from_w_bit(w),
+                segment: None,
+            });
+            let acc = decode_register(0b000, w);
+            Instruction {
                 op: Op::Mov,
-                operands: [Some(mem), Some(ax)],
-                length: 2 + w,
-            };
-
-            instruction
+                operands: [Some(mem), Some(acc)],
+                length: 3,
+                prefixes: Prefixes::default(),
+            }
         }
         // ADD | reg/memory with register to either
         (0, 0, 0, 0, 0, 0, d, w) => {
@@ -217,14 +240,14 @@ pub fn decode_instruction(bytes: &[u8]) -> Instruction {
                 _ => unreachable!(),
             };
 
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Add,
                 operands: [Some(dest), Some(src)],
                 length: len,
-            };
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
-        // ADD/SUB/CMP | immediate to register/memory
+        // ADD/OR/ADC/SBB/AND/SUB/XOR/CMP | immediate to register/memory
         (1, 0, 0, 0, 0, 0, s, w) => {
             let mod_bits = bytes[1] >> 6;
             let rm = bytes[1] & 0x07;
@@ -290,19 +313,13 @@ pub fn decode_instruction(bytes: &[u8]) -> Instruction {
                 _ => unreachable!(),
             };
             let op_code = bytes[1] & 0b00111000;
-            let op = match op_code {
-                0b00000000 => Op::Add,
-                0b00101000 => Op::Sub,
-                0b00111000 => Op::Cmp,
-                _ => unreachable!(),
-            };
-            let instruction = Instruction {
-                op: op,
+            let op = decode_alu_op(op_code >> 3);
+            Instruction {
+                op,
                 operands: [Some(dest), Some(src)],
                 length: len,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // ADD | immediate to accumulator
         (0, 0, 0, 0, 0, 1, 0, w) => {
@@ -320,13 +337,12 @@ pub fn decode_instruction(bytes: &[u8]) -> Instruction {
                 (ax, operand)
             };
 
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Add,
                 operands: [Some(reg), Some(imm)],
                 length: 2 + w,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // SUB | reg/memory and register to either
         (0, 0, 1, 0, 1, 0, d, w) => {
@@ -360,12 +376,12 @@ pub fn decode_instruction(bytes: &[u8]) -> Instruction {
                 _ => unreachable!(),
             };
 
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Sub,
                 operands: [Some(dest), Some(src)],
                 length: len,
-            };
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // SUB | immediate from accumulator
         (0, 0, 1, 0, 1, 1, 0, w) => {
@@ -383,13 +399,12 @@ pub fn decode_instruction(bytes: &[u8]) -> Instruction {
                 (ax, operand)
             };
 
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Sub,
                 operands: [Some(reg), Some(imm)],
                 length: 2 + w,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // CMP | register/memory with register
         (0, 0, 1, 1, 1, 0, d, w) => {
@@ -423,12 +438,12 @@ pub fn decode_instruction(bytes: &[u8]) -> Instruction {
                 _ => unreachable!(),
             };
 
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Cmp,
                 operands: [Some(dest), Some(src)],
                 length: len,
-            };
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // CMP | immediate to accumulator
         (0, 0, 1, 1, 1, 1, 0, w) => {
@@ -446,235 +461,701 @@ pub fn decode_instruction(bytes: &[u8]) -> Instruction {
                 (ax, operand)
             };
 
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Cmp,
                 operands: [Some(reg), Some(imm)],
                 length: 2 + w,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // OR/ADC/SBB/AND/XOR | reg/memory and register to either
+        (0, 0, o2, o1, o0, 0, d, w) => {
+            let (dest, src, len) = decode_reg_mod_rm(&bytes[1..], d, w);
+            Instruction {
+                op: decode_alu_op((o2 << 2) + (o1 << 1) + o0),
+                operands: [Some(dest), Some(src)],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // OR/ADC/SBB/AND/XOR | immediate to accumulator
+        (0, 0, o2, o1, o0, 1, 0, w) => {
+            let acc = decode_register(0b000, w);
+            let (imm, len) = decode_immediate(&bytes[1..], w);
+            Instruction {
+                op: decode_alu_op((o2 << 2) + (o1 << 1) + o0),
+                operands: [Some(acc), Some(imm)],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // TEST | register/memory and register
+        (1, 0, 0, 0, 0, 1, 0, w) => {
+            let (dest, src, len) = decode_reg_mod_rm(&bytes[1..], 0, w);
+            Instruction {
+                op: Op::Test,
+                operands: [Some(dest), Some(src)],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // TEST | immediate data and accumulator
+        (1, 0, 1, 0, 1, 0, 0, w) => {
+            let acc = decode_register(0b000, w);
+            let (imm, len) = decode_immediate(&bytes[1..], w);
+            Instruction {
+                op: Op::Test,
+                operands: [Some(acc), Some(imm)],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // SHL/SHR/SAR/ROL/ROR/RCL/RCR
+        (1, 1, 0, 1, 0, 0, v, w) => {
+            let (dest, len) = decode_mod_rm(&bytes[1..], w);
+            let count = if v == 0 {
+                Operand::Immediate(Immediate::Bit8(1))
+            } else {
+                Operand::Register(Register::CL)
             };
-
-            instruction
+            let op = match bytes[1] & 0b00111000 {
+                0b00000000 => Op::Rol,
+                0b00001000 => Op::Ror,
+                0b00010000 => Op::Rcl,
+                0b00011000 => Op::Rcr,
+                // 110 is undocumented, treated as SHL.
+                0b00100000 | 0b00110000 => Op::Shl,
+                0b00101000 => Op::Shr,
+                0b00111000 => Op::Sar,
+                _ => unreachable!(),
+            };
+            Instruction {
+                op,
+                operands: [Some(dest), Some(count)],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // TEST | immediate data and register/memory
+        (1, 1, 1, 1, 0, 1, 1, w) if bytes[1] & 0b00110000 == 0 => {
+            let (dest, len) = decode_mod_rm(&bytes[1..], w);
+            let (imm, imm_len) = decode_immediate(&bytes[1 + len as usize..], w);
+            Instruction {
+                op: Op::Test,
+                operands: [Some(dest), Some(imm)],
+                length: 1 + len + imm_len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // NOT/NEG
+        (1, 1, 1, 1, 0, 1, 1, w) if bytes[1] & 0b00110000 == 0b00010000 => {
+            let (dest, len) = decode_mod_rm(&bytes[1..], w);
+            let op = if bytes[1] & 0b00001000 == 0 {
+                Op::Not
+            } else {
+                Op::Neg
+            };
+            Instruction {
+                op,
+                operands: [Some(dest), None],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
         }
         // JE
         (0, 1, 1, 1, 0, 1, 0, 0) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Je,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JL
         (0, 1, 1, 1, 1, 1, 0, 0) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jl,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JLE
         (0, 1, 1, 1, 1, 1, 1, 0) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jle,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JB
         (0, 1, 1, 1, 0, 0, 1, 0) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jb,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JBE
         (0, 1, 1, 1, 0, 1, 1, 0) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jbe,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JP
         (0, 1, 1, 1, 1, 0, 1, 0) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jp,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JO
         (0, 1, 1, 1, 0, 0, 0, 0) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jo,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JS
         (0, 1, 1, 1, 1, 0, 0, 0) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Js,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JNE/JNZ
         (0, 1, 1, 1, 0, 1, 0, 1) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jne,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JNL
         (0, 1, 1, 1, 1, 1, 0, 1) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jnl,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JG
         (0, 1, 1, 1, 1, 1, 1, 1) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jg,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JNB
         (0, 1, 1, 1, 0, 0, 1, 1) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jnb,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JA
         (0, 1, 1, 1, 0, 1, 1, 1) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Ja,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JNP
         (0, 1, 1, 1, 1, 0, 1, 1) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jnp,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JNO
         (0, 1, 1, 1, 0, 0, 0, 1) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jno,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JNS
         (0, 1, 1, 1, 1, 0, 0, 1) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jns,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // LOOP
         (1, 1, 1, 0, 0, 0, 1, 0) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Loop,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // LOOPZ
         (1, 1, 1, 0, 0, 0, 0, 1) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Loopz,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // LOOPNZ
         (1, 1, 1, 0, 0, 0, 0, 0) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Loopnz,
                 operands: [Some(ip_inc), None],
                 length: 2,
-            };
-
-            instruction
+                prefixes: Prefixes::default(),
+            }
         }
         // JCXZ
         (1, 1, 1, 0, 0, 0, 1, 1) => {
             let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
-            let instruction = Instruction {
+            Instruction {
                 op: Op::Jcxz,
                 operands: [Some(ip_inc), None],
                 length: 2,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // IN | Fixed port
+        (1, 1, 1, 0, 0, 1, 0, w) => {
+            let acc = decode_register(0b000, w);
+            let port = Operand::Immediate(Immediate::Bit8(bytes[1]));
+            Instruction {
+                op: Op::In,
+                operands: [Some(acc), Some(port)],
+                length: 2,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // IN | Variable port
+        (1, 1, 1, 0, 1, 1, 0, w) => {
+            let acc = decode_register(0b000, w);
+            let port = Operand::Register(Register::DX);
+            Instruction {
+                op: Op::In,
+                operands: [Some(acc), Some(port)],
+                length: 1,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // OUT | Fixed port
+        (1, 1, 1, 0, 0, 1, 1, w) => {
+            let acc = decode_register(0b000, w);
+            let port = Operand::Immediate(Immediate::Bit8(bytes[1]));
+            Instruction {
+                op: Op::Out,
+                operands: [Some(port), Some(acc)],
+                length: 2,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // OUT | Variable port
+        (1, 1, 1, 0, 1, 1, 1, w) => {
+            let acc = decode_register(0b000, w);
+            let port = Operand::Register(Register::DX);
+            Instruction {
+                op: Op::Out,
+                operands: [Some(port), Some(acc)],
+                length: 1,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // INT | Type specified
+        (1, 1, 0, 0, 1, 1, 0, 1) => {
+            let vector = Operand::Immediate(Immediate::Bit8(bytes[1]));
+            Instruction {
+                op: Op::Int,
+                operands: [Some(vector), None],
+                length: 2,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // INT | Type 3
+        (1, 1, 0, 0, 1, 1, 0, 0) => Instruction {
+            op: Op::Int3,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // INTO
+        (1, 1, 0, 0, 1, 1, 1, 0) => Instruction {
+            op: Op::Into,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // IRET
+        (1, 1, 0, 0, 1, 1, 1, 1) => Instruction {
+            op: Op::Iret,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // CLI
+        (1, 1, 1, 1, 1, 0, 1, 0) => Instruction {
+            op: Op::Cli,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // STI
+        (1, 1, 1, 1, 1, 0, 1, 1) => Instruction {
+            op: Op::Sti,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // CLC
+        (1, 1, 1, 1, 1, 0, 0, 0) => Instruction {
+            op: Op::Clc,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // STC
+        (1, 1, 1, 1, 1, 0, 0, 1) => Instruction {
+            op: Op::Stc,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // CMC
+        (1, 1, 1, 1, 0, 1, 0, 1) => Instruction {
+            op: Op::Cmc,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // CLD
+        (1, 1, 1, 1, 1, 1, 0, 0) => Instruction {
+            op: Op::Cld,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // STD
+        (1, 1, 1, 1, 1, 1, 0, 1) => Instruction {
+            op: Op::Std,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // WAIT
+        (1, 0, 0, 1, 1, 0, 1, 1) => Instruction {
+            op: Op::Wait,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // MOVS
+        (1, 0, 1, 0, 0, 1, 0, w) => Instruction {
+            op: if w == 0 { Op::Movsb } else { Op::Movsw },
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // CMPS
+        (1, 0, 1, 0, 0, 1, 1, w) => Instruction {
+            op: if w == 0 { Op::Cmpsb } else { Op::Cmpsw },
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // SCAS
+        (1, 0, 1, 0, 1, 1, 1, w) => Instruction {
+            op: if w == 0 { Op::Scasb } else { Op::Scasw },
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // LODS
+        (1, 0, 1, 0, 1, 1, 0, w) => Instruction {
+            op: if w == 0 { Op::Lodsb } else { Op::Lodsw },
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // STOS
+        (1, 0, 1, 0, 1, 0, 1, w) => Instruction {
+            op: if w == 0 { Op::Stosb } else { Op::Stosw },
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // MUL/IMUL/DIV/IDIV
+        (1, 1, 1, 1, 0, 1, 1, w) if bytes[1] & 0b00100000 != 0 => {
+            let (operand, len) = decode_mod_rm(&bytes[1..], w);
+            let op = match bytes[1] & 0b00111000 {
+                0b00100000 => Op::Mul,
+                0b00101000 => Op::Imul,
+                0b00110000 => Op::Div,
+                0b00111000 => Op::Idiv,
+                _ => unreachable!(),
             };
-
-            instruction
+            Instruction {
+                op,
+                operands: [Some(operand), None],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // DAA/DAS/AAA/AAS
+        (0, 0, 1, a, s, 1, 1, 1) => {
+            let op = match (a, s) {
+                (0, 0) => Op::Daa,
+                (0, 1) => Op::Das,
+                (1, 0) => Op::Aaa,
+                (1, 1) => Op::Aas,
+                _ => unreachable!(),
+            };
+            Instruction {
+                op,
+                operands: [None, None],
+                length: 1,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // AAM/AAD
+        (1, 1, 0, 1, 0, 1, 0, d) => {
+            let base = Operand::Immediate(Immediate::Bit8(bytes[1]));
+            Instruction {
+                op: if d == 0 { Op::Aam } else { Op::Aad },
+                operands: [Some(base), None],
+                length: 2,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // INC/DEC | Register
+        (0, 1, 0, 0, d, r2, r1, r0) => {
+            let reg = decode_register((r2 << 2) + (r1 << 1) + r0, 1);
+            Instruction {
+                op: if d == 0 { Op::Inc } else { Op::Dec },
+                operands: [Some(reg), None],
+                length: 1,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // INC/DEC | Register/memory
+        (1, 1, 1, 1, 1, 1, 1, w) if bytes[1] & 0b00110000 == 0 => {
+            let (dest, len) = decode_mod_rm(&bytes[1..], w);
+            Instruction {
+                op: if bytes[1] & 0b00001000 == 0 {
+                    Op::Inc
+                } else {
+                    Op::Dec
+                },
+                operands: [Some(dest), None],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // XCHG | Register/memory with register
+        (1, 0, 0, 0, 0, 1, 1, w) => {
+            let (dest, src, len) = decode_reg_mod_rm(&bytes[1..], 0, w);
+            Instruction {
+                op: Op::Xchg,
+                operands: [Some(dest), Some(src)],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // NOP, encoded as XCHG AX, AX
+        (1, 0, 0, 1, 0, 0, 0, 0) => Instruction {
+            op: Op::Nop,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // XCHG | Register with accumulator
+        (1, 0, 0, 1, 0, r2, r1, r0) => {
+            let reg = decode_register((r2 << 2) + (r1 << 1) + r0, 1);
+            Instruction {
+                op: Op::Xchg,
+                operands: [Some(Operand::Register(Register::AX)), Some(reg)],
+                length: 1,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // LEA
+        (1, 0, 0, 0, 1, 1, 0, 1) => {
+            let (dest, src, len) = decode_reg_mod_rm(&bytes[1..], 1, 1);
+            Instruction {
+                op: Op::Lea,
+                operands: [Some(dest), Some(src)],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // LES/LDS
+        (1, 1, 0, 0, 0, 1, 0, d) => {
+            let (dest, src, len) = decode_reg_mod_rm(&bytes[1..], 1, 1);
+            Instruction {
+                op: if d == 0 { Op::Les } else { Op::Lds },
+                operands: [Some(dest), Some(src)],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // CBW
+        (1, 0, 0, 1, 1, 0, 0, 0) => Instruction {
+            op: Op::Cbw,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // CWD
+        (1, 0, 0, 1, 1, 0, 0, 1) => Instruction {
+            op: Op::Cwd,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // XLAT
+        (1, 1, 0, 1, 0, 1, 1, 1) => Instruction {
+            op: Op::Xlat,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // LAHF
+        (1, 0, 0, 1, 1, 1, 1, 1) => Instruction {
+            op: Op::Lahf,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // SAHF
+        (1, 0, 0, 1, 1, 1, 1, 0) => Instruction {
+            op: Op::Sahf,
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // JMP | Direct within segment, short
+        (1, 1, 1, 0, 1, 0, 1, 1) => {
+            let ip_inc = Operand::Immediate(Immediate::Bit8(bytes[1]));
+            Instruction {
+                op: Op::Jmp,
+                operands: [Some(ip_inc), None],
+                length: 2,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // JMP/CALL | Direct within segment
+        (1, 1, 1, 0, 1, 0, 0, c) => {
+            let ip_inc =
+                Operand::Immediate(Immediate::Bit16(u16::from_le_bytes([bytes[1], bytes[2]])));
+            Instruction {
+                op: if c == 0 { Op::Call } else { Op::Jmp },
+                operands: [Some(ip_inc), None],
+                length: 3,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // JMP | Direct intersegment
+        (1, 1, 1, 0, 1, 0, 1, 0) => Instruction {
+            op: Op::Jmp,
+            operands: [Some(decode_pointer(&bytes[1..])), None],
+            length: 5,
+            prefixes: Prefixes::default(),
+        },
+        // CALL | Direct intersegment
+        (1, 0, 0, 1, 1, 0, 1, 0) => Instruction {
+            op: Op::Call,
+            operands: [Some(decode_pointer(&bytes[1..])), None],
+            length: 5,
+            prefixes: Prefixes::default(),
+        },
+        // CALL/JMP | Indirect within segment or intersegment
+        (1, 1, 1, 1, 1, 1, 1, 1) if matches!((bytes[1] >> 3) & 0b111, 0b010..=0b101) => {
+            let reg = (bytes[1] >> 3) & 0b111;
+            let (mut target, len) = decode_mod_rm(&bytes[1..], 1);
+            if let Operand::Memory(mem) = &mut target {
+                if reg & 1 == 1 {
+                    mem.size = MemoryOperandSize::Far;
+                }
+            }
+            Instruction {
+                op: if reg < 0b100 { Op::Call } else { Op::Jmp },
+                operands: [Some(target), None],
+                length: 1 + len,
+                prefixes: Prefixes::default(),
+            }
+        }
+        // RET/RETF
+        (1, 1, 0, 0, f, 0, 1, 1) => Instruction {
+            op: if f == 0 { Op::Ret } else { Op::Retf },
+            operands: [None, None],
+            length: 1,
+            prefixes: Prefixes::default(),
+        },
+        // RET/RETF | Adding immediate to SP
+        (1, 1, 0, 0, f, 0, 1, 0) => {
+            let count =
+                Operand::Immediate(Immediate::Bit16(u16::from_le_bytes([bytes[1], bytes[2]])));
+            Instruction {
+                op: if f == 0 { Op::Ret } else { Op::Retf },
+                operands: [Some(count), None],
+                length: 3,
+                prefixes: Prefixes::default(),
+            }
         }
-        _ => unimplemented!(),
+        _ => db(bytes[0]),
+    }
+}
+
+fn db(byte: u8) -> Instruction {
+    Instruction {
+        op: Op::Db,
+        operands: [Some(Operand::Immediate(Immediate::Bit8(byte))), None],
+        length: 1,
+        prefixes: Prefixes::default(),
     }
 }
 
@@ -706,6 +1187,85 @@ fn decode_register(encoding: u8, wide: u8) -> Operand {
     }
 }
 
+fn decode_segment(encoding: u8) -> SegmentRegister {
+    match encoding {
+        0b00 => SegmentRegister::ES,
+        0b01 => SegmentRegister::CS,
+        0b10 => SegmentRegister::SS,
+        0b11 => SegmentRegister::DS,
+        _ => unreachable!(),
+    }
+}
+
+fn decode_pointer(bytes: &[u8]) -> Operand {
+    Operand::Pointer {
+        offset: u16::from_le_bytes([bytes[0], bytes[1]]),
+        segment: u16::from_le_bytes([bytes[2], bytes[3]]),
+    }
+}
+
+fn decode_alu_op(encoding: u8) -> Op {
+    match encoding {
+        0b000 => Op::Add,
+        0b001 => Op::Or,
+        0b010 => Op::Adc,
+        0b011 => Op::Sbb,
+        0b100 => Op::And,
+        0b101 => Op::Sub,
+        0b110 => Op::Xor,
+        0b111 => Op::Cmp,
+        _ => unreachable!(),
+    }
+}
+
+/// Decodes an immediate of width `w` at `bytes[0]`, returning the operand
+/// and its length.
+fn decode_immediate(bytes: &[u8], w: u8) -> (Operand, u8) {
+    if w == 0 {
+        (Operand::Immediate(Immediate::Bit8(bytes[0])), 1)
+    } else {
+        let data = u16::from_le_bytes([bytes[0], bytes[1]]);
+        (Operand::Immediate(Immediate::Bit16(data)), 2)
+    }
+}
+
+/// Decodes both operands of the mod-reg-r/m byte at `bytes[0]` as
+/// `(dest, src)`, honoring the direction bit. Also returns the number of
+/// bytes taken by mod-reg-r/m and displacement.
+fn decode_reg_mod_rm(bytes: &[u8], d: u8, w: u8) -> (Operand, Operand, u8) {
+    let reg = decode_register((bytes[0] & 0x38) >> 3, w);
+    let (rm, len) = decode_mod_rm(bytes, w);
+    if d == 1 {
+        (reg, rm, len)
+    } else {
+        (rm, reg, len)
+    }
+}
+
+/// Decodes the r/m field of the mod-reg-r/m byte at `bytes[0]`. Returns the
+/// operand and the number of bytes taken by mod-reg-r/m and displacement.
+fn decode_mod_rm(bytes: &[u8], w: u8) -> (Operand, u8) {
+    let mod_bits = bytes[0] >> 6;
+    let rm = bytes[0] & 0x07;
+    match mod_bits {
+        // Memory Mode
+        0b00 => {
+            let (mem, bytes_read) = decode_address(rm, w, &bytes[1..3]);
+            (mem, 1 + bytes_read)
+        }
+        // Memory Mode, 8bit displacement
+        0b01 => (decode_address_disp8(rm, w, bytes[1] as i8), 2),
+        // Memory Mode, 16bit displacement
+        0b10 => {
+            let disp16 = i16::from_le_bytes([bytes[1], bytes[2]]);
+            (decode_address_disp16(rm, w, disp16), 3)
+        }
+        // Register Mode
+        0b11 => (decode_register(rm, w), 1),
+        _ => unreachable!(),
+    }
+}
+
 fn decode_address(encoding: u8, w: u8, displacement_bytes: &[u8]) -> (Operand, u8) {
     let mut bytes_read = 0;
     let operand_size = MemoryOperandSize::from_w_bit(w);
@@ -729,6 +1289,7 @@ fn decode_address(encoding: u8, w: u8, displacement_bytes: &[u8]) -> (Operand, u
     let operand = Operand::Memory(MemoryOperand {
         kind: operand_kind,
         size: operand_size,
+        segment: None,
     });
     (operand, bytes_read)
 }
@@ -746,11 +1307,11 @@ fn decode_address_disp8(encoding: u8, w: u8, disp: i8) -> Operand {
         0b111 => MemoryOperandKind::Disp8_BX(disp),
         _ => unreachable!(),
     };
-    let operand = Operand::Memory(MemoryOperand {
+    Operand::Memory(MemoryOperand {
         kind: operand_kind,
         size: operand_size,
-    });
-    operand
+        segment: None,
+    })
 }
 
 fn decode_address_disp16(encoding: u8, w: u8, disp: i16) -> Operand {
@@ -766,16 +1327,18 @@ fn decode_address_disp16(encoding: u8, w: u8, disp: i16) -> Operand {
         0b111 => MemoryOperandKind::Disp16_BX(disp),
         _ => unreachable!(),
     };
-    let operand = Operand::Memory(MemoryOperand {
+    Operand::Memory(MemoryOperand {
         kind: operand_kind,
         size: operand_size,
-    });
-    operand
+        segment: None,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
     #[test]
     fn register_to_register() {
         let bytes = &mut [0x89, 0xd9, 0, 0, 0, 0];
@@ -788,6 +1351,7 @@ mod tests {
                 Some(Operand::Register(Register::BX)),
             ],
             length: 2,
+            prefixes: Prefixes::default(),
         };
         assert_eq!(instruction, answer);
         assert_eq!(instruction.to_string(), "mov cx, bx");
@@ -805,9 +1369,11 @@ mod tests {
                 Some(Operand::Memory(MemoryOperand {
                     kind: MemoryOperandKind::Direct_Address(33),
                     size: MemoryOperandSize::Word,
+                    segment: None,
                 })),
             ],
             length: 3,
+            prefixes: Prefixes::default(),
         };
 
         assert_eq!(instruction, answer);
@@ -826,9 +1392,11 @@ mod tests {
                 Some(Operand::Memory(MemoryOperand {
                     kind: MemoryOperandKind::Disp8_SI(33),
                     size: MemoryOperandSize::Word,
+                    segment: None,
                 })),
             ],
             length: 3,
+            prefixes: Prefixes::default(),
         };
         assert_eq!(instruction, answer);
         assert_eq!(instruction.to_string(), "mov bx, word [si +33]");
@@ -846,11 +1414,268 @@ mod tests {
                 Some(Operand::Memory(MemoryOperand {
                     kind: MemoryOperandKind::Disp16_SI(2000),
                     size: MemoryOperandSize::Word,
+                    segment: None,
                 })),
             ],
             length: 4,
+            prefixes: Prefixes::default(),
         };
         assert_eq!(instruction, answer);
         assert_eq!(instruction.to_string(), "mov dx, word [si +2000]");
     }
+
+    #[test]
+    fn rep_prefixed_string() {
+        let instruction = decode_instruction(&[0xf3, 0xa4, 0, 0, 0, 0]);
+        assert_eq!(instruction.op, Op::Movsb);
+        assert_eq!(instruction.prefixes.rep, Some(Rep::Rep));
+        assert_eq!(instruction.length, 2);
+        assert_eq!(instruction.to_string(), "rep movsb");
+
+        let instruction = decode_instruction(&[0xf3, 0xa7, 0, 0, 0, 0]);
+        assert_eq!(instruction.to_string(), "repe cmpsw");
+
+        let instruction = decode_instruction(&[0xf2, 0xae, 0, 0, 0, 0]);
+        assert_eq!(instruction.to_string(), "repne scasb");
+    }
+
+    #[test]
+    fn group_opcodes() {
+        let cases: &[(&[u8], &str)] = &[
+            (&[0xd1, 0xe0], "shl ax, 1"),
+            (&[0xd2, 0x6f, 0x02], "shr byte [bx +2], cl"),
+            (&[0xd3, 0x1e, 0x34, 0x12], "rcr word [4660], cl"),
+            (&[0xf6, 0xc3, 0x80], "test bl, byte 128"),
+            (&[0xf7, 0x1c], "neg word [si]"),
+            (&[0x81, 0xe1, 0xff, 0x00], "and cx, word 255"),
+            (&[0x83, 0xf0, 0x01], "xor ax, byte 1"),
+            (&[0x0b, 0x46, 0xfe], "or ax, word [bp -2]"),
+            (&[0x24, 0x0f], "and al, byte 15"),
+        ];
+        for (bytes, text) in cases {
+            let mut padded = [0; 6];
+            padded[..bytes.len()].copy_from_slice(bytes);
+            let instruction = decode_instruction(&padded);
+            assert_eq!(instruction.length as usize, bytes.len());
+            assert_eq!(instruction.to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn decimal_adjust() {
+        let instruction = decode_instruction(&[0x27, 0, 0, 0, 0, 0]);
+        assert_eq!(instruction.to_string(), "daa");
+        let instruction = decode_instruction(&[0x3f, 0, 0, 0, 0, 0]);
+        assert_eq!(instruction.to_string(), "aas");
+        let instruction = decode_instruction(&[0xd4, 0x0a, 0, 0, 0, 0]);
+        assert_eq!(instruction.to_string(), "aam");
+        let instruction = decode_instruction(&[0xd5, 0x10, 0, 0, 0, 0]);
+        assert_eq!(instruction.to_string(), "aad 16");
+    }
+
+    #[test]
+    fn data_movement() {
+        let cases: &[(&[u8], &str)] = &[
+            (&[0x47], "inc di"),
+            (&[0x4d], "dec bp"),
+            (&[0xfe, 0x07], "inc byte [bx]"),
+            (&[0xff, 0x4e, 0x02], "dec word [bp +2]"),
+            (&[0x86, 0xe0], "xchg al, ah"),
+            (&[0x87, 0x1e, 0x00, 0x01], "xchg word [256], bx"),
+            (&[0x93], "xchg ax, bx"),
+            (&[0x8d, 0x40, 0x04], "lea ax, word [bx + si +4]"),
+            (&[0xc5, 0x36, 0x10, 0x00], "lds si, word [16]"),
+            (&[0xc4, 0x3f], "les di, word [bx]"),
+            (&[0x98], "cbw"),
+            (&[0x99], "cwd"),
+            (&[0xd7], "xlat"),
+            (&[0x9f], "lahf"),
+            (&[0x9e], "sahf"),
+        ];
+        for (bytes, text) in cases {
+            let mut padded = [0; 6];
+            padded[..bytes.len()].copy_from_slice(bytes);
+            let instruction = decode_instruction(&padded);
+            assert_eq!(instruction.length as usize, bytes.len());
+            assert_eq!(instruction.to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn control_transfer() {
+        let cases: &[(&[u8], &str)] = &[
+            (&[0xeb, 0xfe], "jmp short $+0"),
+            (&[0xe9, 0x00, 0x01], "jmp near $+259"),
+            (&[0xea, 0x78, 0x56, 0x34, 0x12], "jmp 4660:22136"),
+            (&[0xff, 0xe3], "jmp bx"),
+            (&[0xff, 0x27], "jmp word [bx]"),
+            (&[0xff, 0x6e, 0x04], "jmp far [bp +4]"),
+            (&[0xe8, 0xfd, 0xff], "call $+0"),
+            (&[0x9a, 0x00, 0x00, 0x00, 0xf0], "call 61440:0"),
+            (&[0xff, 0xd0], "call ax"),
+            (&[0xff, 0x1e, 0x10, 0x00], "call far [16]"),
+            (&[0xc3], "ret"),
+            (&[0xc2, 0x04, 0x00], "ret 4"),
+            (&[0xcb], "retf"),
+            (&[0xca, 0x02, 0x00], "retf 2"),
+        ];
+        for (bytes, text) in cases {
+            let mut padded = [0; 6];
+            padded[..bytes.len()].copy_from_slice(bytes);
+            let instruction = decode_instruction(&padded);
+            assert_eq!(instruction.length as usize, bytes.len());
+            assert_eq!(instruction.to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn flag_control_and_lock() {
+        let cases: &[(&[u8], &str)] = &[
+            (&[0xf8], "clc"),
+            (&[0xf9], "stc"),
+            (&[0xf5], "cmc"),
+            (&[0xfc], "cld"),
+            (&[0xfd], "std"),
+            (&[0xfa], "cli"),
+            (&[0xfb], "sti"),
+            (&[0x90], "nop"),
+            (&[0x9b], "wait"),
+            (&[0xf0, 0x86, 0x07], "lock xchg byte [bx], al"),
+            (&[0xf0, 0xff, 0x06, 0x00, 0x01], "lock inc word [256]"),
+        ];
+        for (bytes, text) in cases {
+            let mut padded = [0; 6];
+            padded[..bytes.len()].copy_from_slice(bytes);
+            let instruction = decode_instruction(&padded);
+            assert_eq!(instruction.length as usize, bytes.len());
+            assert_eq!(instruction.to_string(), *text);
+        }
+
+        let instruction = decode_instruction(&[0xf0, 0xf3, 0xa5, 0, 0, 0]);
+        assert!(instruction.prefixes.lock);
+        assert_eq!(instruction.prefixes.rep, Some(Rep::Rep));
+        assert_eq!(instruction.to_string(), "lock rep movsw");
+    }
+
+    #[test]
+    fn prefix_sequences() {
+        let cases: &[(&[u8], &str)] = &[
+            (&[0x26, 0x8b, 0x07], "mov ax, word es:[bx]"),
+            (&[0x2e, 0x88, 0x46, 0x02], "mov byte cs:[bp +2], al"),
+            (
+                &[0x36, 0xc7, 0x06, 0x34, 0x12, 0x78, 0x56],
+                "mov word ss:[4660], word 22136",
+            ),
+            (&[0x3e, 0xff, 0x2f], "jmp far ds:[bx]"),
+            (&[0x26, 0xa4], "es movsb"),
+            (&[0xf3, 0x2e, 0xa5], "cs rep movsw"),
+            (&[0xf0, 0x26, 0xf3, 0xaa], "lock es rep stosb"),
+            (&[0xf0, 0x36, 0x01, 0x1c], "lock add word ss:[si], bx"),
+        ];
+        for (bytes, text) in cases {
+            let mut padded = [0; 16];
+            padded[..bytes.len()].copy_from_slice(bytes);
+            let instruction = decode_instruction(&padded);
+            assert_eq!(instruction.length as usize, bytes.len());
+            assert_eq!(instruction.to_string(), *text);
+        }
+
+        let instruction = decode_instruction(&[0x26, 0xf3, 0xa4, 0, 0, 0]);
+        assert_eq!(instruction.op, Op::Movsb);
+        assert_eq!(instruction.prefixes.segment, Some(SegmentRegister::ES));
+        assert_eq!(instruction.prefixes.rep, Some(Rep::Rep));
+
+        // The last segment override wins.
+        let instruction = decode_instruction(&[0x26, 0x2e, 0x8b, 0x07]);
+        assert_eq!(instruction.to_string(), "mov ax, word cs:[bx]");
+
+        // Prefixes running off the end of the input are followed by zeros.
+        let instruction = decode_instruction(&[0xf0; 20]);
+        assert_eq!(instruction.length, 22);
+        assert_eq!(instruction.op, Op::Add);
+    }
+
+    #[test]
+    fn long_prefix_runs() {
+        // The longest run of prefixes whose length still fits in a u8.
+        let mut bytes = vec![0xf3; u8::MAX as usize - MAX_INSTRUCTION_LENGTH];
+        bytes.push(0xa4);
+        let instruction = decode_instruction(&bytes);
+        assert_eq!(instruction.op, Op::Movsb);
+        assert_eq!(instruction.length as usize, bytes.len());
+
+        // One more and the run ends as data.
+        bytes.insert(0, 0xf3);
+        assert_eq!(decode_instruction(&bytes).op, Op::Db);
+    }
+
+    #[test]
+    fn accumulator_bytes() {
+        let instruction = decode_instruction(&[0xa0, 0x34, 0x12]);
+        assert_eq!(instruction.length, 3);
+        assert_eq!(instruction.to_string(), "mov al, byte [4660]");
+
+        let instruction = decode_instruction(&[0xa2, 0x34, 0x12]);
+        assert_eq!(instruction.length, 3);
+        assert_eq!(instruction.to_string(), "mov byte [4660], al");
+    }
+
+    #[test]
+    fn unknown_opcodes_are_data() {
+        let instruction = decode_instruction(&[0x0f, 0x01]);
+        assert_eq!(instruction.op, Op::Db);
+        assert_eq!(instruction.length, 1);
+        assert_eq!(instruction.to_string(), "db 15");
+
+        // The prefix is data too, the opcode after it starts afresh.
+        let instruction = decode_instruction(&[0x26, 0x0f]);
+        assert_eq!(instruction.to_string(), "db 38");
+        assert_eq!(instruction.prefixes, Prefixes::default());
+
+        // FE only has INC and DEC.
+        assert_eq!(decode_instruction(&[0xfe, 0xd0]).op, Op::Db);
+    }
+
+    #[test]
+    fn short_input() {
+        assert_eq!(
+            decode_instruction(&[]).to_string(),
+            "add byte [bx + si], al"
+        );
+        let instruction = decode_instruction(&[0xb8, 0x34]);
+        assert_eq!(instruction.length, 3);
+        assert_eq!(instruction.to_string(), "mov ax, word 52");
+    }
+
+    fn is_prefix(byte: &u8) -> bool {
+        matches!(byte, 0x26 | 0x2e | 0x36 | 0x3e | 0xf0 | 0xf2 | 0xf3)
+    }
+
+    proptest! {
+        #[test]
+        fn decodes_any_bytes(bytes in prop::collection::vec(any::<u8>(), 0..24)) {
+            let instruction = decode_instruction(&bytes);
+
+            // The 8086 takes any number of prefixes, so only the bytes after
+            // them are held to 1-6; the prefix run itself is bounded by
+            // long_prefix_runs.
+            let prefix_len = bytes.iter().take_while(|byte| is_prefix(byte)).count();
+            let length = instruction.length as usize;
+            if instruction.op == Op::Db {
+                prop_assert_eq!(length, 1);
+            } else {
+                prop_assert!((1..=MAX_INSTRUCTION_LENGTH).contains(&(length - prefix_len)));
+                // Nothing past the instruction's own bytes is looked at.
+                let own = &bytes[..length.min(bytes.len())];
+                prop_assert_eq!(decode_instruction(own), instruction);
+            }
+
+            for syntax in [Syntax::Nasm, Syntax::Masm, Syntax::Intel, Syntax::Att] {
+                for radix in [Radix::Decimal, Radix::Hex] {
+                    let options = FormatOptions { syntax, radix, signed: true };
+                    instruction.display(options).to_string();
+                }
+            }
+        }
+    }
 }
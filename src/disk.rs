@@ -0,0 +1,317 @@
+use crate::simulator::physical_address;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::*;
+
+pub const SECTOR_SIZE: usize = 512;
+
+/// A raw floppy image served through the BIOS disk interrupt.
+///
+/// The geometry is picked from the image size for the standard PC floppy
+/// formats; anything else is treated as a 1.44M disk and sectors past the
+/// end of the image read as not found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disk {
+    image: Vec<u8>,
+    /// BIOS drive number, 00h for the first floppy.
+    pub drive: u8,
+    pub cylinders: u16,
+    pub heads: u8,
+    pub sectors_per_track: u8,
+    status: u8,
+}
+
+impl Disk {
+    pub const INVALID_FUNCTION: u8 = 0x01;
+    pub const SECTOR_NOT_FOUND: u8 = 0x04;
+    pub const TIMEOUT: u8 = 0x80;
+
+    pub fn new(image: Vec<u8>, drive: u8) -> Self {
+        let (cylinders, heads, sectors_per_track) = match image.len() / 1024 {
+            160 => (40, 1, 8),
+            180 => (40, 1, 9),
+            320 => (40, 2, 8),
+            360 => (40, 2, 9),
+            720 => (80, 2, 9),
+            1200 => (80, 2, 15),
+            2880 => (80, 2, 36),
+            _ => (80, 2, 18),
+        };
+        Self {
+            image,
+            drive,
+            cylinders,
+            heads,
+            sectors_per_track,
+            status: 0,
+        }
+    }
+
+    pub fn sector(&self, lba: usize) -> Option<&[u8]> {
+        let start = lba * SECTOR_SIZE;
+        self.image.get(start..start + SECTOR_SIZE)
+    }
+
+    /// Runs the INT 13h function selected by AH.
+    ///
+    /// Supports 00h reset, 01h status, 02h read sectors and 08h drive
+    /// parameters. Errors set CF and return the status in AH.
+    pub fn service(&mut self, registers: &mut RegisterFile, memory: &mut dyn Bus) {
+        let function = (registers.ax >> 8) as u8;
+        let drive = registers.dx as u8;
+
+        let result = match function {
+            // Status of the last operation, which this call leaves alone.
+            0x01 => match self.status {
+                0 => Ok(0),
+                status => Err(status),
+            },
+            _ if drive != self.drive => Err(Self::TIMEOUT),
+            0x00 => Ok(0),
+            0x02 => self.read(registers, memory),
+            0x08 => {
+                let max_cylinder = self.cylinders - 1;
+                registers.bx = (registers.bx & 0xff00) | self.drive_type() as u16;
+                registers.cx = (max_cylinder & 0xff) << 8
+                    | (max_cylinder >> 2) & 0xc0
+                    | self.sectors_per_track as u16;
+                registers.dx = ((self.heads as u16 - 1) << 8) | 1;
+                Ok(0)
+            }
+            _ => Err(Self::INVALID_FUNCTION),
+        };
+        if function != 0x01 {
+            self.status = result.err().unwrap_or(0);
+        }
+
+        // AH holds the status, AL the sectors transferred by a read.
+        let (status, transferred) = match result {
+            Ok(count) => (0, count),
+            Err(status) => (status, 0),
+        };
+        let al = if function == 0x02 {
+            transferred
+        } else {
+            registers.ax as u8
+        };
+        registers.ax = (status as u16) << 8 | al as u16;
+        if status == 0 {
+            registers.flags &= !RegisterFile::CF_MASK;
+        } else {
+            registers.flags |= RegisterFile::CF_MASK;
+        }
+    }
+
+    /// AH=02h: reads AL sectors from CH/CL/DH to ES:BX. Returns the number
+    /// of sectors transferred.
+    fn read(&self, registers: &RegisterFile, memory: &mut dyn Bus) -> Result<u8, u8> {
+        let count = registers.ax as u8;
+        let cylinder = (registers.cx >> 8) | (registers.cx & 0xc0) << 2;
+        let sector = (registers.cx & 0x3f) as u8;
+        let head = (registers.dx >> 8) as u8;
+
+        if count == 0
+            || sector == 0
+            || sector > self.sectors_per_track
+            || head >= self.heads
+            || cylinder >= self.cylinders
+        {
+            return Err(Self::SECTOR_NOT_FOUND);
+        }
+
+        let lba = (cylinder as usize * self.heads as usize + head as usize)
+            * self.sectors_per_track as usize
+            + sector as usize
+            - 1;
+        let mut offset = registers.bx;
+        for i in 0..count as usize {
+            let data = self.sector(lba + i).ok_or(Self::SECTOR_NOT_FOUND)?;
+            for &byte in data {
+                memory.write8(physical_address(registers.es, offset), byte);
+                offset = offset.wrapping_add(1);
+            }
+        }
+        Ok(count)
+    }
+
+    pub(crate) fn save(&self, w: &mut SnapshotWriter) {
+        w.u8(self.drive);
+        w.u16(self.cylinders);
+        w.u8(self.heads);
+        w.u8(self.sectors_per_track);
+        w.u8(self.status);
+        w.u32(self.image.len() as u32);
+        w.bytes(&self.image);
+    }
+
+    pub(crate) fn restore(r: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let drive = r.u8()?;
+        let cylinders = r.u16()?;
+        let heads = r.u8()?;
+        let sectors_per_track = r.u8()?;
+        let status = r.u8()?;
+        // Function 08h reports the geometry less one.
+        if cylinders == 0 || heads == 0 || sectors_per_track == 0 {
+            return Err(SnapshotError::Corrupt);
+        }
+        let len = r.u32()? as usize;
+        Ok(Self {
+            image: r.bytes(len)?.to_vec(),
+            drive,
+            cylinders,
+            heads,
+            sectors_per_track,
+            status,
+        })
+    }
+
+    /// CMOS drive type reported by function 08h.
+    fn drive_type(&self) -> u8 {
+        match (self.cylinders, self.sectors_per_track) {
+            (40, _) => 1,
+            (80, 15) => 2,
+            (80, 9) => 3,
+            (80, 36) => 5,
+            _ => 4,
+        }
+    }
+}
+
+/// Loads the first sector of the disk at 0000:7C00 and returns the register
+/// state the BIOS hands over with: CS:IP at the sector, DL the boot drive
+/// and the stack just below the sector.
+pub fn load_boot_sector(disk: &Disk, memory: &mut [u8]) -> Result<RegisterFile, LoadError> {
+    let sector = disk.sector(0).ok_or(LoadError::Truncated)?;
+    let target = memory
+        .get_mut(0x7c00..0x7c00 + SECTOR_SIZE)
+        .ok_or(LoadError::TooLarge)?;
+    target.copy_from_slice(sector);
+
+    Ok(RegisterFile {
+        ip: 0x7c00,
+        sp: 0x7c00,
+        dx: disk.drive as u16,
+        flags: RegisterFile::IF_MASK,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 360K image with every sector filled with its LBA.
+    fn floppy() -> Disk {
+        let mut image = vec![0; 360 * 1024];
+        for (lba, sector) in image.chunks_mut(SECTOR_SIZE).enumerate() {
+            sector.fill(lba as u8);
+        }
+        Disk::new(image, 0)
+    }
+
+    #[test]
+    fn boots_sector_zero() {
+        let mut disk = floppy();
+        disk.image[0] = 0xeb;
+        let mut memory = Memory::default();
+        let registers = load_boot_sector(&disk, &mut memory).unwrap();
+
+        assert_eq!((registers.cs, registers.ip), (0x0000, 0x7c00));
+        assert_eq!(registers.dx & 0xff, 0);
+        assert_eq!(memory[0x7c00], 0xeb);
+        assert!(load_boot_sector(&Disk::new(vec![0; 100], 0), &mut memory).is_err());
+
+        // Too little memory to hold the sector.
+        assert_eq!(
+            load_boot_sector(&disk, &mut [0; 0x7d00]).unwrap_err(),
+            LoadError::TooLarge
+        );
+    }
+
+    #[test]
+    fn reads_sectors_through_int13() {
+        let mut disk = floppy();
+        assert_eq!(
+            (disk.cylinders, disk.heads, disk.sectors_per_track),
+            (40, 2, 9)
+        );
+
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            es: 0x1000,
+            bx: 0x0200,
+            ..Default::default()
+        };
+
+        // Two sectors from cylinder 1, head 1, sector 9: LBA 35 and 36.
+        registers.ax = 0x0202;
+        registers.cx = 0x0109;
+        registers.dx = 0x0100;
+        disk.service(&mut registers, &mut memory);
+        assert_eq!(registers.ax, 0x0002);
+        assert_eq!(registers.flags & RegisterFile::CF_MASK, 0);
+        assert_eq!(memory[0x10200], 35);
+        assert_eq!(memory[0x103ff], 35);
+        assert_eq!(memory[0x10400], 36);
+        assert_eq!(memory[0x105ff], 36);
+
+        // Sector 10 does not exist on a 9 sector track.
+        registers.ax = 0x0201;
+        registers.cx = 0x000a;
+        registers.dx = 0x0000;
+        disk.service(&mut registers, &mut memory);
+        assert_eq!(registers.ax >> 8, Disk::SECTOR_NOT_FOUND as u16);
+        assert_ne!(registers.flags & RegisterFile::CF_MASK, 0);
+
+        // The failure is reported by the status call.
+        registers.ax = 0x0100;
+        disk.service(&mut registers, &mut memory);
+        assert_eq!(registers.ax >> 8, Disk::SECTOR_NOT_FOUND as u16);
+
+        // Reset clears it.
+        registers.ax = 0x0000;
+        disk.service(&mut registers, &mut memory);
+        assert_eq!(registers.ax >> 8, 0);
+        assert_eq!(registers.flags & RegisterFile::CF_MASK, 0);
+
+        // Nothing on drive 1.
+        registers.ax = 0x0201;
+        registers.cx = 0x0001;
+        registers.dx = 0x0001;
+        disk.service(&mut registers, &mut memory);
+        assert_eq!(registers.ax >> 8, Disk::TIMEOUT as u16);
+    }
+
+    #[test]
+    fn drive_parameters() {
+        let mut disk = Disk::new(vec![0; 1440 * 1024], 0);
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ax: 0x0800,
+            ..Default::default()
+        };
+        disk.service(&mut registers, &mut memory);
+        assert_eq!(registers.bx & 0xff, 4);
+        assert_eq!(registers.cx, 0x4f12);
+        assert_eq!(registers.dx, 0x0101);
+    }
+
+    #[test]
+    fn int13_from_code() {
+        let mut io = IoBus::default();
+        io.disk = Some(floppy());
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ax: 0x0201,
+            bx: 0x0500,
+            cx: 0x0002,
+            ..Default::default()
+        };
+
+        // int 13h is serviced without going through the vector table.
+        let instruction = decode_instruction(&[0xcd, 0x13, 0, 0, 0, 0]);
+        simulate(&mut registers, &mut memory, &mut io, instruction);
+        assert_eq!(registers.ip, 2);
+        assert_eq!(registers.ax, 0x0001);
+        assert_eq!(memory[0x500], 1);
+    }
+}
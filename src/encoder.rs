@@ -0,0 +1,874 @@
+use crate::*;
+
+/// Encodes `instruction` as machine code, or returns None when no encoding
+/// decodes back to it. Where there is more than one encoding the shortest
+/// is used and each prefix is written once, so the result can be shorter
+/// than `instruction.length`. [`Op::Db`] encodes its byte as is.
+pub fn encode_instruction(instruction: &Instruction) -> Option<Vec<u8>> {
+    let prefixes = instruction.prefixes;
+    let mut bytes = Vec::with_capacity(MAX_INSTRUCTION_LENGTH + 3);
+    if prefixes.lock {
+        bytes.push(0xf0);
+    }
+    match prefixes.rep {
+        Some(Rep::Rep) => bytes.push(0xf3),
+        Some(Rep::Repne) => bytes.push(0xf2),
+        None => {}
+    }
+    if let Some(segment) = prefixes.segment {
+        bytes.push(0x26 | encode_segment(segment) << 3);
+    }
+    // The decoder copies the override into every memory operand.
+    for operand in instruction.operands.iter().flatten() {
+        if let Operand::Memory(mem) = operand {
+            if mem.segment != prefixes.segment {
+                return None;
+            }
+        }
+    }
+    if instruction.op == Op::Db && !bytes.is_empty() {
+        return None;
+    }
+
+    bytes.extend(encode_operation(instruction.op, instruction.operands)?);
+    Some(bytes)
+}
+
+fn encode_operation(op: Op, operands: [Option<Operand>; 2]) -> Option<Vec<u8>> {
+    use Operand::{Immediate as Imm, Memory as Mem, Register as Reg};
+
+    let bytes = match (op, operands) {
+        (_, [None, None]) => vec![implied_opcode(op)?],
+        (Op::Db, [Some(Imm(Immediate::Bit8(byte))), None]) => vec![byte],
+
+        // MOV | Memory to accumulator, accumulator to memory
+        (Op::Mov, [Some(Reg(acc @ (Register::AL | Register::AX))), Some(Mem(mem))])
+            if matches!(mem.kind, MemoryOperandKind::Direct_Address(_)) =>
+        {
+            let w = same_width(Reg(acc), Mem(mem))?;
+            with_address(0xa0 | w, mem)
+        }
+        (Op::Mov, [Some(Mem(mem)), Some(Reg(acc @ (Register::AL | Register::AX)))])
+            if matches!(mem.kind, MemoryOperandKind::Direct_Address(_)) =>
+        {
+            let w = same_width(Reg(acc), Mem(mem))?;
+            with_address(0xa2 | w, mem)
+        }
+        // MOV | Immediate to register
+        (Op::Mov, [Some(Reg(reg)), Some(Imm(imm))]) => {
+            let w = same_width(Reg(reg), Imm(imm))?;
+            let mut bytes = vec![0xb0 | w << 3 | register_code(reg)];
+            push_immediate(&mut bytes, imm);
+            bytes
+        }
+        // MOV | Immediate to memory
+        (Op::Mov, [Some(dest), Some(Imm(imm))]) => {
+            let w = same_width(dest, Imm(imm))?;
+            let mut bytes = mod_rm(0xc6 | w, 0b000, dest)?;
+            push_immediate(&mut bytes, imm);
+            bytes
+        }
+        // MOV/ALU | Register/memory to/from register
+        (Op::Mov, [Some(dest), Some(Reg(src))]) => reg_mod_rm(0x88, src, dest)?,
+        (Op::Mov, [Some(Reg(dest)), Some(src @ Mem(_))]) => reg_mod_rm(0x8a, dest, src)?,
+        (_, [Some(dest), Some(Reg(src))]) if alu_code(op).is_some() => {
+            reg_mod_rm(alu_code(op)? << 3, src, dest)?
+        }
+        (_, [Some(Reg(dest)), Some(src @ Mem(_))]) if alu_code(op).is_some() => {
+            reg_mod_rm(alu_code(op)? << 3 | 0b10, dest, src)?
+        }
+        // ALU | Immediate to accumulator
+        (_, [Some(Reg(acc @ (Register::AL | Register::AX))), Some(Imm(imm))])
+            if alu_code(op).is_some() && same_width(Reg(acc), Imm(imm)).is_some() =>
+        {
+            let w = same_width(Reg(acc), Imm(imm))?;
+            let mut bytes = vec![alu_code(op)? << 3 | 0b100 | w];
+            push_immediate(&mut bytes, imm);
+            bytes
+        }
+        // ALU | Immediate to register/memory, sign extended by 83h
+        (_, [Some(dest), Some(Imm(imm @ Immediate::Bit8(_)))])
+            if alu_code(op).is_some() && width(dest) == Some(1) =>
+        {
+            let mut bytes = mod_rm(0x83, alu_code(op)?, dest)?;
+            push_immediate(&mut bytes, imm);
+            bytes
+        }
+        (_, [Some(dest), Some(Imm(imm))]) if alu_code(op).is_some() => {
+            let w = same_width(dest, Imm(imm))?;
+            let mut bytes = mod_rm(0x80 | w, alu_code(op)?, dest)?;
+            push_immediate(&mut bytes, imm);
+            bytes
+        }
+
+        // TEST
+        (Op::Test, [Some(Reg(acc @ (Register::AL | Register::AX))), Some(Imm(imm))]) => {
+            let w = same_width(Reg(acc), Imm(imm))?;
+            let mut bytes = vec![0xa8 | w];
+            push_immediate(&mut bytes, imm);
+            bytes
+        }
+        (Op::Test, [Some(dest), Some(Imm(imm))]) => {
+            let w = same_width(dest, Imm(imm))?;
+            let mut bytes = mod_rm(0xf6 | w, 0b000, dest)?;
+            push_immediate(&mut bytes, imm);
+            bytes
+        }
+        (Op::Test, [Some(dest), Some(Reg(src))]) => reg_mod_rm(0x84, src, dest)?,
+
+        // XCHG
+        (Op::Xchg, [Some(Reg(Register::AX)), Some(Reg(reg))])
+            if reg != Register::AX && reg.width() == RegisterWidth::Word =>
+        {
+            vec![0x90 | register_code(reg)]
+        }
+        (Op::Xchg, [Some(dest), Some(Reg(src))]) => reg_mod_rm(0x86, src, dest)?,
+
+        // SHL/SHR/SAR/ROL/ROR/RCL/RCR
+        (_, [Some(dest), Some(count)]) if shift_code(op).is_some() => {
+            let v = match count {
+                Imm(Immediate::Bit8(1)) => 0,
+                Reg(Register::CL) => 1,
+                _ => return None,
+            };
+            mod_rm(0xd0 | v << 1 | width(dest)?, shift_code(op)?, dest)?
+        }
+
+        // NOT/NEG/MUL/IMUL/DIV/IDIV
+        (Op::Not | Op::Neg | Op::Mul | Op::Imul | Op::Div | Op::Idiv, [Some(dest), None]) => {
+            let code = match op {
+                Op::Not => 0b010,
+                Op::Neg => 0b011,
+                Op::Mul => 0b100,
+                Op::Imul => 0b101,
+                Op::Div => 0b110,
+                _ => 0b111,
+            };
+            mod_rm(0xf6 | width(dest)?, code, dest)?
+        }
+
+        // INC/DEC
+        (Op::Inc | Op::Dec, [Some(Reg(reg)), None]) if reg.width() == RegisterWidth::Word => {
+            let d = u8::from(op == Op::Dec);
+            vec![0x40 | d << 3 | register_code(reg)]
+        }
+        (Op::Inc | Op::Dec, [Some(dest), None]) => {
+            mod_rm(0xfe | width(dest)?, u8::from(op == Op::Dec), dest)?
+        }
+
+        // Conditional jumps and loops
+        (_, [Some(Imm(Immediate::Bit8(rel))), None]) if short_branch_opcode(op).is_some() => {
+            vec![short_branch_opcode(op)?, rel]
+        }
+
+        // JMP/CALL
+        (Op::Jmp, [Some(Imm(Immediate::Bit16(rel))), None]) => {
+            let [lo, hi] = rel.to_le_bytes();
+            vec![0xe9, lo, hi]
+        }
+        (Op::Call, [Some(Imm(Immediate::Bit16(rel))), None]) => {
+            let [lo, hi] = rel.to_le_bytes();
+            vec![0xe8, lo, hi]
+        }
+        (Op::Jmp | Op::Call, [Some(Operand::Pointer { segment, offset }), None]) => {
+            let mut bytes = vec![if op == Op::Jmp { 0xea } else { 0x9a }];
+            bytes.extend(offset.to_le_bytes());
+            bytes.extend(segment.to_le_bytes());
+            bytes
+        }
+        (Op::Jmp | Op::Call, [Some(target), None]) => {
+            let far = match target {
+                Mem(mem) => match mem.size {
+                    MemoryOperandSize::Byte => return None,
+                    MemoryOperandSize::Word => 0,
+                    MemoryOperandSize::Far => 1,
+                },
+                Reg(reg) if reg.width() == RegisterWidth::Word => 0,
+                _ => return None,
+            };
+            let code = if op == Op::Call { 0b010 } else { 0b100 };
+            mod_rm(0xff, code | far, target)?
+        }
+
+        // RET/RETF | Adding immediate to SP
+        (Op::Ret | Op::Retf, [Some(Imm(Immediate::Bit16(count))), None]) => {
+            let [lo, hi] = count.to_le_bytes();
+            vec![if op == Op::Ret { 0xc2 } else { 0xca }, lo, hi]
+        }
+
+        // IN/OUT
+        (Op::In, [Some(Reg(acc @ (Register::AL | Register::AX))), Some(port)])
+        | (Op::Out, [Some(port), Some(Reg(acc @ (Register::AL | Register::AX)))]) => {
+            let w = width(Reg(acc))?;
+            let out = u8::from(op == Op::Out) << 1;
+            match port {
+                Imm(Immediate::Bit8(port)) => vec![0xe4 | out | w, port],
+                Reg(Register::DX) => vec![0xec | out | w],
+                _ => return None,
+            }
+        }
+
+        // INT/AAM/AAD
+        (Op::Int | Op::Aam | Op::Aad, [Some(Imm(Immediate::Bit8(value))), None]) => {
+            let opcode = match op {
+                Op::Int => 0xcd,
+                Op::Aam => 0xd4,
+                _ => 0xd5,
+            };
+            vec![opcode, value]
+        }
+
+        // LEA/LES/LDS
+        (Op::Lea | Op::Les | Op::Lds, [Some(Reg(dest)), Some(src)])
+            if dest.width() == RegisterWidth::Word =>
+        {
+            let opcode = match op {
+                Op::Lea => 0x8d,
+                Op::Les => 0xc4,
+                _ => 0xc5,
+            };
+            // The w bit of these opcodes is fixed, only words are decoded.
+            same_width(Reg(dest), src)?;
+            mod_rm(opcode, register_code(dest), src)?
+        }
+
+        _ => return None,
+    };
+    Some(bytes)
+}
+
+/// Opcodes of the instructions taking no operands.
+fn implied_opcode(op: Op) -> Option<u8> {
+    let opcode = match op {
+        Op::Daa => 0x27,
+        Op::Das => 0x2f,
+        Op::Aaa => 0x37,
+        Op::Aas => 0x3f,
+        Op::Nop => 0x90,
+        Op::Cbw => 0x98,
+        Op::Cwd => 0x99,
+        Op::Wait => 0x9b,
+        Op::Sahf => 0x9e,
+        Op::Lahf => 0x9f,
+        Op::Movsb => 0xa4,
+        Op::Movsw => 0xa5,
+        Op::Cmpsb => 0xa6,
+        Op::Cmpsw => 0xa7,
+        Op::Stosb => 0xaa,
+        Op::Stosw => 0xab,
+        Op::Lodsb => 0xac,
+        Op::Lodsw => 0xad,
+        Op::Scasb => 0xae,
+        Op::Scasw => 0xaf,
+        Op::Ret => 0xc3,
+        Op::Retf => 0xcb,
+        Op::Int3 => 0xcc,
+        Op::Into => 0xce,
+        Op::Iret => 0xcf,
+        Op::Xlat => 0xd7,
+        Op::Cmc => 0xf5,
+        Op::Clc => 0xf8,
+        Op::Stc => 0xf9,
+        Op::Cli => 0xfa,
+        Op::Sti => 0xfb,
+        Op::Cld => 0xfc,
+        Op::Std => 0xfd,
+        _ => return None,
+    };
+    Some(opcode)
+}
+
+/// Opcodes of the branches taking an 8-bit displacement.
+fn short_branch_opcode(op: Op) -> Option<u8> {
+    let opcode = match op {
+        Op::Jo => 0x70,
+        Op::Jno => 0x71,
+        Op::Jb => 0x72,
+        Op::Jnb => 0x73,
+        Op::Je => 0x74,
+        Op::Jne => 0x75,
+        Op::Jbe => 0x76,
+        Op::Ja => 0x77,
+        Op::Js => 0x78,
+        Op::Jns => 0x79,
+        Op::Jp => 0x7a,
+        Op::Jnp => 0x7b,
+        Op::Jl => 0x7c,
+        Op::Jnl => 0x7d,
+        Op::Jle => 0x7e,
+        Op::Jg => 0x7f,
+        Op::Loopnz => 0xe0,
+        Op::Loopz => 0xe1,
+        Op::Loop => 0xe2,
+        Op::Jcxz => 0xe3,
+        Op::Jmp => 0xeb,
+        _ => return None,
+    };
+    Some(opcode)
+}
+
+/// The inverse of `decode_alu_op`.
+fn alu_code(op: Op) -> Option<u8> {
+    let code = match op {
+        Op::Add => 0b000,
+        Op::Or => 0b001,
+        Op::Adc => 0b010,
+        Op::Sbb => 0b011,
+        Op::And => 0b100,
+        Op::Sub => 0b101,
+        Op::Xor => 0b110,
+        Op::Cmp => 0b111,
+        _ => return None,
+    };
+    Some(code)
+}
+
+fn shift_code(op: Op) -> Option<u8> {
+    let code = match op {
+        Op::Rol => 0b000,
+        Op::Ror => 0b001,
+        Op::Rcl => 0b010,
+        Op::Rcr => 0b011,
+        Op::Shl => 0b100,
+        Op::Shr => 0b101,
+        Op::Sar => 0b111,
+        _ => return None,
+    };
+    Some(code)
+}
+
+fn register_code(reg: Register) -> u8 {
+    match reg {
+        Register::AL | Register::AX => 0b000,
+        Register::CL | Register::CX => 0b001,
+        Register::DL | Register::DX => 0b010,
+        Register::BL | Register::BX => 0b011,
+        Register::AH | Register::SP => 0b100,
+        Register::CH | Register::BP => 0b101,
+        Register::DH | Register::SI => 0b110,
+        Register::BH | Register::DI => 0b111,
+    }
+}
+
+fn encode_segment(segment: SegmentRegister) -> u8 {
+    match segment {
+        SegmentRegister::ES => 0b00,
+        SegmentRegister::CS => 0b01,
+        SegmentRegister::SS => 0b10,
+        SegmentRegister::DS => 0b11,
+    }
+}
+
+/// The w bit for `operand`: 0 for bytes, 1 for words, None for anything
+/// else.
+fn width(operand: Operand) -> Option<u8> {
+    match operand {
+        Operand::Register(reg) => match reg.width() {
+            RegisterWidth::Byte => Some(0),
+            RegisterWidth::Word => Some(1),
+        },
+        Operand::Memory(mem) => match mem.size {
+            MemoryOperandSize::Byte => Some(0),
+            MemoryOperandSize::Word => Some(1),
+            MemoryOperandSize::Far => None,
+        },
+        Operand::Immediate(Immediate::Bit8(_)) => Some(0),
+        Operand::Immediate(Immediate::Bit16(_)) => Some(1),
+        Operand::Pointer { .. } => None,
+    }
+}
+
+/// The w bit shared by both operands, if they are the same size.
+fn same_width(a: Operand, b: Operand) -> Option<u8> {
+    width(a).filter(|&w| width(b) == Some(w))
+}
+
+fn push_immediate(bytes: &mut Vec<u8>, imm: Immediate) {
+    match imm {
+        Immediate::Bit8(value) => bytes.push(value),
+        Immediate::Bit16(value) => bytes.extend(value.to_le_bytes()),
+    }
+}
+
+fn with_address(opcode: u8, mem: MemoryOperand) -> Vec<u8> {
+    let MemoryOperandKind::Direct_Address(addr) = mem.kind else {
+        unreachable!()
+    };
+    let [lo, hi] = addr.to_le_bytes();
+    vec![opcode, lo, hi]
+}
+
+/// `opcode` with its d bit clear and w bit set from the operands, then
+/// mod-reg-r/m naming `reg` and `rm`.
+fn reg_mod_rm(opcode: u8, reg: Register, rm: Operand) -> Option<Vec<u8>> {
+    let w = same_width(Operand::Register(reg), rm)?;
+    mod_rm(opcode | w, register_code(reg), rm)
+}
+
+/// `opcode`, then mod-reg-r/m with `reg` in the reg field and `rm` in the
+/// r/m field, then any displacement.
+fn mod_rm(opcode: u8, reg: u8, rm: Operand) -> Option<Vec<u8>> {
+    let (mode, code, displacement) = match rm {
+        Operand::Register(rm) => (0b11, register_code(rm), vec![]),
+        Operand::Memory(mem) => address_fields(mem.kind),
+        _ => return None,
+    };
+    let mut bytes = vec![opcode, mode << 6 | reg << 3 | code];
+    bytes.extend(displacement);
+    Some(bytes)
+}
+
+/// The mod and r/m fields and displacement bytes of a memory operand.
+fn address_fields(kind: MemoryOperandKind) -> (u8, u8, Vec<u8>) {
+    use MemoryOperandKind::*;
+    match kind {
+        Direct_BX_SI => (0b00, 0b000, vec![]),
+        Direct_BX_DI => (0b00, 0b001, vec![]),
+        Direct_BP_SI => (0b00, 0b010, vec![]),
+        Direct_BP_DI => (0b00, 0b011, vec![]),
+        Direct_SI => (0b00, 0b100, vec![]),
+        Direct_DI => (0b00, 0b101, vec![]),
+        Direct_Address(addr) => (0b00, 0b110, addr.to_le_bytes().to_vec()),
+        Direct_BX => (0b00, 0b111, vec![]),
+
+        Disp8_BX_SI(disp) => (0b01, 0b000, vec![disp as u8]),
+        Disp8_BX_DI(disp) => (0b01, 0b001, vec![disp as u8]),
+        Disp8_BP_SI(disp) => (0b01, 0b010, vec![disp as u8]),
+        Disp8_BP_DI(disp) => (0b01, 0b011, vec![disp as u8]),
+        Disp8_SI(disp) => (0b01, 0b100, vec![disp as u8]),
+        Disp8_DI(disp) => (0b01, 0b101, vec![disp as u8]),
+        Disp8_BP(disp) => (0b01, 0b110, vec![disp as u8]),
+        Disp8_BX(disp) => (0b01, 0b111, vec![disp as u8]),
+
+        Disp16_BX_SI(disp) => (0b10, 0b000, disp.to_le_bytes().to_vec()),
+        Disp16_BX_DI(disp) => (0b10, 0b001, disp.to_le_bytes().to_vec()),
+        Disp16_BP_SI(disp) => (0b10, 0b010, disp.to_le_bytes().to_vec()),
+        Disp16_BP_DI(disp) => (0b10, 0b011, disp.to_le_bytes().to_vec()),
+        Disp16_SI(disp) => (0b10, 0b100, disp.to_le_bytes().to_vec()),
+        Disp16_DI(disp) => (0b10, 0b101, disp.to_le_bytes().to_vec()),
+        Disp16_BP(disp) => (0b10, 0b110, disp.to_le_bytes().to_vec()),
+        Disp16_BX(disp) => (0b10, 0b111, disp.to_le_bytes().to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn encode(bytes: &[u8]) -> Vec<u8> {
+        encode_instruction(&decode_instruction(bytes)).unwrap()
+    }
+
+    #[test]
+    fn shortest_form() {
+        // mov ax, [0x100] through mod-reg-r/m and the accumulator form.
+        assert_eq!(encode(&[0x8b, 0x06, 0x00, 0x01]), [0xa1, 0x00, 0x01]);
+        // add al, 5 and inc cx.
+        assert_eq!(encode(&[0x80, 0xc0, 0x05]), [0x04, 0x05]);
+        assert_eq!(encode(&[0xff, 0xc1]), [0x41]);
+        // Repeated prefixes are written once.
+        assert_eq!(encode(&[0x26, 0xf3, 0x26, 0xa4]), [0xf3, 0x26, 0xa4]);
+        // The undocumented SHL /6 encodes as /4.
+        assert_eq!(encode(&[0xd1, 0xf0]), [0xd1, 0xe0]);
+    }
+
+    #[test]
+    fn rejects_what_does_not_decode() {
+        let mem = MemoryOperand {
+            kind: MemoryOperandKind::Direct_BX,
+            size: MemoryOperandSize::Word,
+            segment: None,
+        };
+        let cases = [
+            // Mismatched sizes.
+            (
+                Op::Mov,
+                [
+                    Some(Operand::Register(Register::AL)),
+                    Some(Operand::Register(Register::BX)),
+                ],
+            ),
+            // No memory to memory forms.
+            (
+                Op::Add,
+                [Some(Operand::Memory(mem)), Some(Operand::Memory(mem))],
+            ),
+            // Shifts by one or CL only.
+            (
+                Op::Shl,
+                [
+                    Some(Operand::Register(Register::AX)),
+                    Some(Operand::Immediate(Immediate::Bit8(2))),
+                ],
+            ),
+            (Op::Mul, [None, None]),
+        ];
+        for (op, operands) in cases {
+            let instruction = Instruction {
+                op,
+                length: 0,
+                operands,
+                prefixes: Prefixes::default(),
+            };
+            assert_eq!(encode_instruction(&instruction), None, "{op:?}");
+        }
+
+        // A memory operand disagreeing with the override.
+        let instruction = Instruction {
+            op: Op::Inc,
+            length: 0,
+            operands: [Some(Operand::Memory(mem)), None],
+            prefixes: Prefixes {
+                segment: Some(SegmentRegister::ES),
+                ..Default::default()
+            },
+        };
+        assert_eq!(encode_instruction(&instruction), None);
+    }
+
+    fn register(w: u8) -> impl Strategy<Value = Register> {
+        let registers = if w == 0 {
+            [
+                Register::AL,
+                Register::CL,
+                Register::DL,
+                Register::BL,
+                Register::AH,
+                Register::CH,
+                Register::DH,
+                Register::BH,
+            ]
+        } else {
+            [
+                Register::AX,
+                Register::CX,
+                Register::DX,
+                Register::BX,
+                Register::SP,
+                Register::BP,
+                Register::SI,
+                Register::DI,
+            ]
+        };
+        prop::sample::select(registers.to_vec())
+    }
+
+    fn memory(size: MemoryOperandSize) -> impl Strategy<Value = MemoryOperand> {
+        (0..24u8, any::<u16>()).prop_map(move |(index, disp)| {
+            use MemoryOperandKind::*;
+            let (disp8, disp16) = (disp as i8, disp as i16);
+            let kind = match index {
+                0 => Direct_BX_SI,
+                1 => Direct_BX_DI,
+                2 => Direct_BP_SI,
+                3 => Direct_BP_DI,
+                4 => Direct_SI,
+                5 => Direct_DI,
+                6 => Direct_Address(disp),
+                7 => Direct_BX,
+                8 => Disp8_BX_SI(disp8),
+                9 => Disp8_BX_DI(disp8),
+                10 => Disp8_BP_SI(disp8),
+                11 => Disp8_BP_DI(disp8),
+                12 => Disp8_SI(disp8),
+                13 => Disp8_DI(disp8),
+                14 => Disp8_BP(disp8),
+                15 => Disp8_BX(disp8),
+                16 => Disp16_BX_SI(disp16),
+                17 => Disp16_BX_DI(disp16),
+                18 => Disp16_BP_SI(disp16),
+                19 => Disp16_BP_DI(disp16),
+                20 => Disp16_SI(disp16),
+                21 => Disp16_DI(disp16),
+                22 => Disp16_BP(disp16),
+                _ => Disp16_BX(disp16),
+            };
+            MemoryOperand {
+                kind,
+                size,
+                segment: None,
+            }
+        })
+    }
+
+    fn size(w: u8) -> MemoryOperandSize {
+        MemoryOperandSize::from_w_bit(w)
+    }
+
+    /// A register or memory operand of width `w`.
+    fn rm(w: u8) -> impl Strategy<Value = Operand> {
+        prop_oneof![
+            register(w).prop_map(Operand::Register),
+            memory(size(w)).prop_map(Operand::Memory),
+        ]
+    }
+
+    fn immediate(w: u8) -> impl Strategy<Value = Operand> {
+        any::<u16>().prop_map(move |value| {
+            Operand::Immediate(if w == 0 {
+                Immediate::Bit8(value as u8)
+            } else {
+                Immediate::Bit16(value)
+            })
+        })
+    }
+
+    fn ops(ops: &[Op]) -> impl Strategy<Value = Op> {
+        prop::sample::select(ops.to_vec())
+    }
+
+    const ALU: [Op; 8] = [
+        Op::Add,
+        Op::Or,
+        Op::Adc,
+        Op::Sbb,
+        Op::And,
+        Op::Sub,
+        Op::Xor,
+        Op::Cmp,
+    ];
+
+    fn one(op: Op, operand: Operand) -> (Op, [Option<Operand>; 2]) {
+        (op, [Some(operand), None])
+    }
+
+    fn two(op: Op, dest: Operand, src: Operand) -> (Op, [Option<Operand>; 2]) {
+        (op, [Some(dest), Some(src)])
+    }
+
+    /// Operations and operands in every shape the decoder produces.
+    fn operation() -> impl Strategy<Value = (Op, [Option<Operand>; 2])> {
+        let width = 0..=1u8;
+
+        prop_oneof![
+            // Register/memory to/from register
+            (
+                ops(&[&ALU[..], &[Op::Mov]].concat()),
+                width.clone(),
+                any::<bool>()
+            )
+                .prop_flat_map(|(op, w, to_register)| {
+                    (rm(w), register(w)).prop_map(move |(rm, reg)| {
+                        if to_register {
+                            two(op, Operand::Register(reg), rm)
+                        } else {
+                            two(op, rm, Operand::Register(reg))
+                        }
+                    })
+                }),
+            (ops(&[Op::Test, Op::Xchg]), width.clone()).prop_flat_map(|(op, w)| {
+                (rm(w), register(w)).prop_map(move |(rm, reg)| two(op, rm, Operand::Register(reg)))
+            }),
+            // Immediate to register/memory
+            (
+                ops(&[&ALU[..], &[Op::Mov, Op::Test]].concat()),
+                width.clone()
+            )
+                .prop_flat_map(
+                    |(op, w)| (rm(w), immediate(w)).prop_map(move |(rm, imm)| two(op, rm, imm))
+                ),
+            (ops(&ALU), rm(1), immediate(0)).prop_map(|(op, rm, imm)| two(op, rm, imm)),
+            // Shifts and rotates
+            (
+                ops(&[
+                    Op::Rol,
+                    Op::Ror,
+                    Op::Rcl,
+                    Op::Rcr,
+                    Op::Shl,
+                    Op::Shr,
+                    Op::Sar
+                ]),
+                width.clone().prop_flat_map(rm),
+                prop_oneof![
+                    Just(Operand::Immediate(Immediate::Bit8(1))),
+                    Just(Operand::Register(Register::CL)),
+                ],
+            )
+                .prop_map(|(op, rm, count)| two(op, rm, count)),
+            // Single register/memory operand
+            (
+                ops(&[
+                    Op::Not,
+                    Op::Neg,
+                    Op::Mul,
+                    Op::Imul,
+                    Op::Div,
+                    Op::Idiv,
+                    Op::Inc,
+                    Op::Dec
+                ]),
+                width.clone().prop_flat_map(rm),
+            )
+                .prop_map(|(op, rm)| one(op, rm)),
+            // Branches
+            (
+                ops(&[
+                    Op::Je,
+                    Op::Jl,
+                    Op::Jle,
+                    Op::Jb,
+                    Op::Jbe,
+                    Op::Jp,
+                    Op::Jo,
+                    Op::Js,
+                    Op::Jne,
+                    Op::Jnl,
+                    Op::Jg,
+                    Op::Jnb,
+                    Op::Ja,
+                    Op::Jnp,
+                    Op::Jno,
+                    Op::Jns,
+                    Op::Loop,
+                    Op::Loopz,
+                    Op::Loopnz,
+                    Op::Jcxz,
+                    Op::Jmp
+                ]),
+                immediate(0),
+            )
+                .prop_map(|(op, rel)| one(op, rel)),
+            (ops(&[Op::Jmp, Op::Call]), immediate(1)).prop_map(|(op, rel)| one(op, rel)),
+            (ops(&[Op::Jmp, Op::Call]), any::<(u16, u16)>()).prop_map(|(op, (segment, offset))| {
+                one(op, Operand::Pointer { segment, offset })
+            }),
+            (
+                ops(&[Op::Jmp, Op::Call]),
+                prop_oneof![
+                    rm(1),
+                    memory(MemoryOperandSize::Far).prop_map(Operand::Memory)
+                ],
+            )
+                .prop_map(|(op, target)| one(op, target)),
+            (ops(&[Op::Ret, Op::Retf]), immediate(1)).prop_map(|(op, count)| one(op, count)),
+            // Ports
+            (
+                width.clone(),
+                prop_oneof![immediate(0), Just(Operand::Register(Register::DX))],
+                any::<bool>(),
+            )
+                .prop_map(|(w, port, out)| {
+                    let acc = Operand::Register(if w == 0 { Register::AL } else { Register::AX });
+                    if out {
+                        two(Op::Out, port, acc)
+                    } else {
+                        two(Op::In, acc, port)
+                    }
+                }),
+            (ops(&[Op::Int, Op::Aam, Op::Aad]), immediate(0)).prop_map(|(op, n)| one(op, n)),
+            (ops(&[Op::Lea, Op::Lds, Op::Les]), register(1), rm(1)).prop_map(|(op, reg, rm)| two(
+                op,
+                Operand::Register(reg),
+                rm
+            )),
+            // No operands
+            ops(&[
+                Op::Int3,
+                Op::Into,
+                Op::Iret,
+                Op::Cli,
+                Op::Sti,
+                Op::Movsb,
+                Op::Movsw,
+                Op::Cmpsb,
+                Op::Cmpsw,
+                Op::Scasb,
+                Op::Scasw,
+                Op::Lodsb,
+                Op::Lodsw,
+                Op::Stosb,
+                Op::Stosw,
+                Op::Daa,
+                Op::Das,
+                Op::Aaa,
+                Op::Aas,
+                Op::Cbw,
+                Op::Cwd,
+                Op::Xlat,
+                Op::Lahf,
+                Op::Sahf,
+                Op::Ret,
+                Op::Retf,
+                Op::Clc,
+                Op::Stc,
+                Op::Cmc,
+                Op::Cld,
+                Op::Std,
+                Op::Nop,
+                Op::Wait
+            ])
+            .prop_map(|op| (op, [None, None])),
+        ]
+    }
+
+    fn prefixes() -> impl Strategy<Value = Prefixes> {
+        (
+            prop::option::of(prop::sample::select(vec![Rep::Rep, Rep::Repne])),
+            any::<bool>(),
+            prop::option::of(prop::sample::select(vec![
+                SegmentRegister::ES,
+                SegmentRegister::CS,
+                SegmentRegister::SS,
+                SegmentRegister::DS,
+            ])),
+        )
+            .prop_map(|(rep, lock, segment)| Prefixes { rep, lock, segment })
+    }
+
+    fn instruction() -> impl Strategy<Value = Instruction> {
+        let data = any::<u8>()
+            .prop_filter("starts an instruction", |&byte| {
+                decode_instruction(&[byte]).op == Op::Db
+            })
+            .prop_map(|byte| Instruction {
+                op: Op::Db,
+                length: 1,
+                operands: [Some(Operand::Immediate(Immediate::Bit8(byte))), None],
+                prefixes: Prefixes::default(),
+            });
+        let code = (operation(), prefixes()).prop_map(|((op, mut operands), prefixes)| {
+            for operand in operands.iter_mut().flatten() {
+                if let Operand::Memory(mem) = operand {
+                    mem.segment = prefixes.segment;
+                }
+            }
+            Instruction {
+                op,
+                length: 0,
+                operands,
+                prefixes,
+            }
+        });
+        prop_oneof![1 => data, 20 => code]
+    }
+
+    proptest! {
+        #[test]
+        fn instructions_round_trip(instruction in instruction()) {
+            let bytes = encode_instruction(&instruction).expect("no encoding");
+            let expected = Instruction {
+                length: bytes.len() as u8,
+                ..instruction
+            };
+            prop_assert_eq!(decode_instruction(&bytes), expected);
+        }
+
+        #[test]
+        fn bytes_round_trip(bytes in prop::collection::vec(any::<u8>(), 0..24)) {
+            let instruction = decode_instruction(&bytes);
+            let encoded = encode_instruction(&instruction).expect("no encoding");
+            prop_assert!(encoded.len() <= instruction.length as usize);
+            if instruction.op == Op::Db {
+                prop_assert_eq!(encoded, &bytes[..1]);
+            } else {
+                let expected = Instruction {
+                    length: encoded.len() as u8,
+                    ..instruction
+                };
+                prop_assert_eq!(decode_instruction(&encoded), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,558 @@
+use std::fmt::{self, Display, Write};
+
+use crate::*;
+
+mod att;
+
+/// Assembler dialect used when rendering instructions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Syntax {
+    /// `mov word [bp -2], 0x10`
+    #[default]
+    Nasm,
+    /// `mov word ptr [bp-2], 10h`, also accepted by TASM. The decoder has
+    /// no symbols, so there is never anything to apply OFFSET to.
+    Masm,
+    /// The Intel manual's notation: MASM style, upper case.
+    Intel,
+    /// `movw $0x10,-0x2(%bp)`, laid out like `objdump -m i8086`.
+    Att,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Radix {
+    #[default]
+    Decimal,
+    Hex,
+}
+
+/// Controls how [`Instruction::display`] renders numbers and operands.
+///
+/// The default reproduces the `Display` impl: NASM syntax, decimal,
+/// immediates printed unsigned.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub syntax: Syntax,
+    pub radix: Radix,
+    /// Print immediates as two's complement signed values. Sign-extended
+    /// immediates, displacements and branch targets are always signed.
+    pub signed: bool,
+}
+
+/// An instruction paired with the options to render it with.
+pub struct Formatted<'a> {
+    instruction: &'a Instruction,
+    options: FormatOptions,
+}
+
+impl Instruction {
+    pub fn display(&self, options: FormatOptions) -> Formatted<'_> {
+        Formatted {
+            instruction: self,
+            options,
+        }
+    }
+}
+
+impl Display for Formatted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.options.syntax {
+            Syntax::Intel => {
+                let mut text = String::new();
+                write_instruction(&mut text, self.instruction, self.options)?;
+                f.write_str(&text.to_uppercase())
+            }
+            Syntax::Att => att::write_instruction(f, self.instruction, self.options),
+            Syntax::Nasm | Syntax::Masm => write_instruction(f, self.instruction, self.options),
+        }
+    }
+}
+
+/// Renders the Intel operand order dialects: NASM, MASM and the manual.
+fn write_instruction(
+    w: &mut impl Write,
+    instruction: &Instruction,
+    options: FormatOptions,
+) -> fmt::Result {
+    let op = instruction.op;
+
+    if instruction.prefixes.lock {
+        write!(w, "lock ")?;
+    }
+    // Overrides on memory operands are written inside the operand.
+    if let Some(segment) = instruction.prefixes.segment {
+        let applied = instruction
+            .operands
+            .iter()
+            .any(|operand| matches!(operand, Some(Operand::Memory(_))));
+        if !applied {
+            write!(w, "{segment} ")?;
+        }
+    }
+    match instruction.prefixes.rep {
+        Some(Rep::Rep) if matches!(op, Op::Cmpsb | Op::Cmpsw | Op::Scasb | Op::Scasw) => {
+            write!(w, "repe ")?
+        }
+        Some(Rep::Rep) => write!(w, "rep ")?,
+        Some(Rep::Repne) => write!(w, "repne ")?,
+        None => {}
+    };
+
+    write!(w, "{}", mnemonic(op))?;
+
+    let word_destination = match instruction.operands[0] {
+        Some(Operand::Register(reg)) => reg.width() == RegisterWidth::Word,
+        Some(Operand::Memory(mem)) => mem.size == MemoryOperandSize::Word,
+        _ => false,
+    };
+    for (i, operand) in instruction.operands.iter().enumerate() {
+        let Some(operand) = *operand else {
+            continue;
+        };
+        let separator = if i == 0 { " " } else { ", " };
+
+        match operand {
+            // The base is implied when it is ten.
+            Operand::Immediate(Immediate::Bit8(10)) if matches!(op, Op::Aam | Op::Aad) => {}
+            // AAM/AAD base, data bytes, bytes popped on return and shift
+            // counts carry no size.
+            Operand::Immediate(imm)
+                if matches!(
+                    op,
+                    Op::Aam
+                        | Op::Aad
+                        | Op::Db
+                        | Op::Ret
+                        | Op::Retf
+                        | Op::Shl
+                        | Op::Shr
+                        | Op::Sar
+                        | Op::Rol
+                        | Op::Ror
+                        | Op::Rcl
+                        | Op::Rcr
+                ) =>
+            {
+                write!(w, "{separator}")?;
+                write_number(w, immediate_value(imm, false), options)?;
+            }
+            Operand::Immediate(imm) if is_relative_branch(op) => {
+                write!(w, "{separator}")?;
+                write_branch_target(w, instruction, imm, options)?;
+            }
+            // 83h sign-extends its byte immediate to the word destination.
+            Operand::Immediate(Immediate::Bit8(value))
+                if i == 1 && word_destination && is_alu(op) =>
+            {
+                write!(w, "{separator}")?;
+                if options.syntax == Syntax::Nasm {
+                    write!(w, "byte ")?;
+                }
+                write_number(w, value as i8 as i32, options)?;
+            }
+            operand => {
+                write!(w, "{separator}")?;
+                write_operand(w, operand, options)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn write_operand(
+    w: &mut impl Write,
+    operand: Operand,
+    options: FormatOptions,
+) -> fmt::Result {
+    match operand {
+        Operand::Register(reg) => write!(w, "{}", register_name(reg)),
+        Operand::Memory(mem) => {
+            write!(w, "{}", size_keyword(mem.size, options.syntax))?;
+            let (base, disp) = address_parts(mem.kind);
+            match mem.segment {
+                Some(segment) => write!(w, " {segment}:")?,
+                // MASM reads a bare [number] as an immediate.
+                None if base.is_none() && options.syntax != Syntax::Nasm => write!(w, " ds:")?,
+                None => write!(w, " ")?,
+            }
+
+            write!(w, "[")?;
+            match (base, disp) {
+                (Some(base), None) if options.syntax == Syntax::Nasm => {
+                    write!(w, "{}", base.replace('+', " + "))?
+                }
+                (Some(base), None) => write!(w, "{base}")?,
+                (None, Some(addr)) => write_number(w, addr, options)?,
+                (Some(base), Some(disp)) => {
+                    if options.syntax == Syntax::Nasm {
+                        write!(w, "{} {}", base.replace('+', " + "), sign(disp))?;
+                    } else {
+                        write!(w, "{base}{}", sign(disp))?;
+                    }
+                    write_number(w, disp.abs(), options)?;
+                }
+                (None, None) => unreachable!(),
+            }
+            write!(w, "]")
+        }
+        Operand::Immediate(imm) => {
+            if options.syntax == Syntax::Nasm {
+                match imm {
+                    Immediate::Bit8(_) => write!(w, "byte ")?,
+                    Immediate::Bit16(_) => write!(w, "word ")?,
+                }
+            }
+            write_number(w, immediate_value(imm, options.signed), options)
+        }
+        Operand::Pointer { segment, offset } => {
+            write_number(w, segment as i32, options)?;
+            write!(w, ":")?;
+            write_number(w, offset as i32, options)
+        }
+    }
+}
+
+/// Relative targets are written against `$`, the start of the instruction,
+/// so the output reassembles to the same bytes.
+fn write_branch_target(
+    w: &mut impl Write,
+    instruction: &Instruction,
+    ip_inc: Immediate,
+    options: FormatOptions,
+) -> fmt::Result {
+    if instruction.op == Op::Jmp {
+        match (ip_inc, options.syntax) {
+            (Immediate::Bit8(_), _) => write!(w, "short ")?,
+            (Immediate::Bit16(_), Syntax::Nasm) => write!(w, "near ")?,
+            (Immediate::Bit16(_), _) => write!(w, "near ptr ")?,
+        }
+    }
+
+    let target = immediate_value(ip_inc, true) + instruction.length as i32;
+    write!(w, "${}", sign(target))?;
+    write_number(w, target.abs(), options)
+}
+
+fn write_number(w: &mut impl Write, value: i32, options: FormatOptions) -> fmt::Result {
+    let magnitude = value.unsigned_abs();
+    if value < 0 {
+        write!(w, "-")?;
+    }
+    match (options.radix, options.syntax) {
+        (Radix::Decimal, _) => write!(w, "{magnitude}"),
+        (Radix::Hex, Syntax::Nasm | Syntax::Att) => write!(w, "{magnitude:#x}"),
+        // A leading digit keeps the number from reading as an identifier.
+        (Radix::Hex, _) => {
+            let digits = format!("{magnitude:x}");
+            if digits.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                write!(w, "0{digits}h")
+            } else {
+                write!(w, "{digits}h")
+            }
+        }
+    }
+}
+
+fn sign(value: i32) -> char {
+    if value < 0 {
+        '-'
+    } else {
+        '+'
+    }
+}
+
+fn immediate_value(imm: Immediate, signed: bool) -> i32 {
+    match (imm, signed) {
+        (Immediate::Bit8(value), false) => value as i32,
+        (Immediate::Bit8(value), true) => value as i8 as i32,
+        (Immediate::Bit16(value), false) => value as i32,
+        (Immediate::Bit16(value), true) => value as i16 as i32,
+    }
+}
+
+fn is_alu(op: Op) -> bool {
+    matches!(
+        op,
+        Op::Add | Op::Or | Op::Adc | Op::Sbb | Op::And | Op::Sub | Op::Xor | Op::Cmp
+    )
+}
+
+fn is_relative_branch(op: Op) -> bool {
+    matches!(
+        op,
+        Op::Je
+            | Op::Jl
+            | Op::Jle
+            | Op::Jb
+            | Op::Jbe
+            | Op::Jp
+            | Op::Jo
+            | Op::Js
+            | Op::Jne
+            | Op::Jnl
+            | Op::Jg
+            | Op::Jnb
+            | Op::Ja
+            | Op::Jnp
+            | Op::Jno
+            | Op::Jns
+            | Op::Loop
+            | Op::Loopz
+            | Op::Loopnz
+            | Op::Jcxz
+            | Op::Jmp
+            | Op::Call
+    )
+}
+
+fn size_keyword(size: MemoryOperandSize, syntax: Syntax) -> &'static str {
+    match (size, syntax) {
+        (MemoryOperandSize::Byte, Syntax::Nasm) => "byte",
+        (MemoryOperandSize::Word, Syntax::Nasm) => "word",
+        (MemoryOperandSize::Far, Syntax::Nasm) => "far",
+        (MemoryOperandSize::Byte, _) => "byte ptr",
+        (MemoryOperandSize::Word, _) => "word ptr",
+        (MemoryOperandSize::Far, _) => "dword ptr",
+    }
+}
+
+/// Splits an addressing mode into its base/index registers and displacement.
+fn address_parts(kind: MemoryOperandKind) -> (Option<&'static str>, Option<i32>) {
+    match kind {
+        MemoryOperandKind::Direct_BX_SI => (Some("bx+si"), None),
+        MemoryOperandKind::Direct_BX_DI => (Some("bx+di"), None),
+        MemoryOperandKind::Direct_BP_SI => (Some("bp+si"), None),
+        MemoryOperandKind::Direct_BP_DI => (Some("bp+di"), None),
+        MemoryOperandKind::Direct_SI => (Some("si"), None),
+        MemoryOperandKind::Direct_DI => (Some("di"), None),
+        MemoryOperandKind::Direct_Address(addr) => (None, Some(addr as i32)),
+        MemoryOperandKind::Direct_BX => (Some("bx"), None),
+
+        MemoryOperandKind::Disp8_BX_SI(disp) => (Some("bx+si"), Some(disp as i32)),
+        MemoryOperandKind::Disp8_BX_DI(disp) => (Some("bx+di"), Some(disp as i32)),
+        MemoryOperandKind::Disp8_BP_SI(disp) => (Some("bp+si"), Some(disp as i32)),
+        MemoryOperandKind::Disp8_BP_DI(disp) => (Some("bp+di"), Some(disp as i32)),
+        MemoryOperandKind::Disp8_SI(disp) => (Some("si"), Some(disp as i32)),
+        MemoryOperandKind::Disp8_DI(disp) => (Some("di"), Some(disp as i32)),
+        MemoryOperandKind::Disp8_BP(disp) => (Some("bp"), Some(disp as i32)),
+        MemoryOperandKind::Disp8_BX(disp) => (Some("bx"), Some(disp as i32)),
+
+        MemoryOperandKind::Disp16_BX_SI(disp) => (Some("bx+si"), Some(disp as i32)),
+        MemoryOperandKind::Disp16_BX_DI(disp) => (Some("bx+di"), Some(disp as i32)),
+        MemoryOperandKind::Disp16_BP_SI(disp) => (Some("bp+si"), Some(disp as i32)),
+        MemoryOperandKind::Disp16_BP_DI(disp) => (Some("bp+di"), Some(disp as i32)),
+        MemoryOperandKind::Disp16_SI(disp) => (Some("si"), Some(disp as i32)),
+        MemoryOperandKind::Disp16_DI(disp) => (Some("di"), Some(disp as i32)),
+        MemoryOperandKind::Disp16_BP(disp) => (Some("bp"), Some(disp as i32)),
+        MemoryOperandKind::Disp16_BX(disp) => (Some("bx"), Some(disp as i32)),
+    }
+}
+
+fn register_name(reg: Register) -> &'static str {
+    match reg {
+        Register::AL => "al",
+        Register::CL => "cl",
+        Register::DL => "dl",
+        Register::BL => "bl",
+        Register::AH => "ah",
+        Register::CH => "ch",
+        Register::DH => "dh",
+        Register::BH => "bh",
+
+        Register::AX => "ax",
+        Register::CX => "cx",
+        Register::DX => "dx",
+        Register::BX => "bx",
+        Register::SP => "sp",
+        Register::BP => "bp",
+        Register::SI => "si",
+        Register::DI => "di",
+    }
+}
+
+fn mnemonic(op: Op) -> &'static str {
+    match op {
+        Op::Mov => "mov",
+        Op::Add => "add",
+        Op::Sub => "sub",
+        Op::Cmp => "cmp",
+        Op::Je => "je",
+        Op::Jl => "jl",
+        Op::Jle => "jle",
+        Op::Jb => "jb",
+        Op::Jbe => "jbe",
+        Op::Jp => "jp",
+        Op::Jo => "jo",
+        Op::Js => "js",
+        Op::Jne => "jne",
+        Op::Jnl => "jnl",
+        Op::Jg => "jg",
+        Op::Jnb => "jnb",
+        Op::Ja => "ja",
+        Op::Jnp => "jnp",
+        Op::Jno => "jno",
+        Op::Jns => "jns",
+        Op::Loop => "loop",
+        Op::Loopz => "loopz",
+        Op::Loopnz => "loopnz",
+        Op::Jcxz => "jcxz",
+        Op::In => "in",
+        Op::Out => "out",
+        Op::Int => "int",
+        Op::Int3 => "int3",
+        Op::Into => "into",
+        Op::Iret => "iret",
+        Op::Cli => "cli",
+        Op::Sti => "sti",
+        Op::Movsb => "movsb",
+        Op::Movsw => "movsw",
+        Op::Cmpsb => "cmpsb",
+        Op::Cmpsw => "cmpsw",
+        Op::Scasb => "scasb",
+        Op::Scasw => "scasw",
+        Op::Lodsb => "lodsb",
+        Op::Lodsw => "lodsw",
+        Op::Stosb => "stosb",
+        Op::Stosw => "stosw",
+        Op::Mul => "mul",
+        Op::Imul => "imul",
+        Op::Div => "div",
+        Op::Idiv => "idiv",
+        Op::Adc => "adc",
+        Op::Sbb => "sbb",
+        Op::And => "and",
+        Op::Or => "or",
+        Op::Xor => "xor",
+        Op::Test => "test",
+        Op::Not => "not",
+        Op::Neg => "neg",
+        Op::Shl => "shl",
+        Op::Shr => "shr",
+        Op::Sar => "sar",
+        Op::Rol => "rol",
+        Op::Ror => "ror",
+        Op::Rcl => "rcl",
+        Op::Rcr => "rcr",
+        Op::Daa => "daa",
+        Op::Das => "das",
+        Op::Aaa => "aaa",
+        Op::Aas => "aas",
+        Op::Aam => "aam",
+        Op::Aad => "aad",
+        Op::Inc => "inc",
+        Op::Dec => "dec",
+        Op::Xchg => "xchg",
+        Op::Lea => "lea",
+        Op::Lds => "lds",
+        Op::Les => "les",
+        Op::Cbw => "cbw",
+        Op::Cwd => "cwd",
+        Op::Xlat => "xlat",
+        Op::Lahf => "lahf",
+        Op::Sahf => "sahf",
+        Op::Jmp => "jmp",
+        Op::Call => "call",
+        Op::Ret => "ret",
+        Op::Retf => "retf",
+        Op::Clc => "clc",
+        Op::Stc => "stc",
+        Op::Cmc => "cmc",
+        Op::Cld => "cld",
+        Op::Std => "std",
+        Op::Nop => "nop",
+        Op::Wait => "wait",
+        Op::Db => "db",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(bytes: &[u8], options: FormatOptions) -> String {
+        let mut padded = [0; 16];
+        padded[..bytes.len()].copy_from_slice(bytes);
+        decode_instruction(&padded).display(options).to_string()
+    }
+
+    #[test]
+    fn signed_contexts() {
+        let options = FormatOptions::default();
+        assert_eq!(render(&[0x75, 0xfc], options), "jne $-2");
+        assert_eq!(render(&[0x76, 0x02], options), "jbe $+4");
+        assert_eq!(render(&[0xe2, 0x80], options), "loop $-126");
+        assert_eq!(render(&[0x83, 0xc0, 0xff], options), "add ax, byte -1");
+        assert_eq!(render(&[0xe5, 0xff], options), "in ax, byte 255");
+        assert_eq!(render(&[0xb0, 0xff], options), "mov al, byte 255");
+
+        let options = FormatOptions {
+            signed: true,
+            ..Default::default()
+        };
+        assert_eq!(render(&[0xb0, 0xff], options), "mov al, byte -1");
+        assert_eq!(render(&[0xb8, 0x00, 0x80], options), "mov ax, word -32768");
+    }
+
+    #[test]
+    fn base_register_spacing() {
+        let options = FormatOptions::default();
+        assert_eq!(render(&[0x00, 0x00], options), "add byte [bx + si], al");
+        assert_eq!(
+            render(&[0x00, 0x40, 0x04], options),
+            "add byte [bx + si +4], al"
+        );
+
+        let masm = FormatOptions {
+            syntax: Syntax::Masm,
+            ..Default::default()
+        };
+        assert_eq!(render(&[0x00, 0x00], masm), "add byte ptr [bx+si], al");
+        assert_eq!(
+            render(&[0x00, 0x40, 0x04], masm),
+            "add byte ptr [bx+si+4], al"
+        );
+    }
+
+    #[test]
+    fn hex_and_syntax() {
+        // mov word [bp - 2], 16
+        let bytes = &[0xc7, 0x46, 0xfe, 0x10, 0x00];
+        let hex = |syntax| FormatOptions {
+            syntax,
+            radix: Radix::Hex,
+            signed: false,
+        };
+
+        assert_eq!(
+            render(bytes, hex(Syntax::Nasm)),
+            "mov word [bp -0x2], word 0x10"
+        );
+        assert_eq!(
+            render(bytes, hex(Syntax::Masm)),
+            "mov word ptr [bp-2h], 10h"
+        );
+        assert_eq!(
+            render(bytes, hex(Syntax::Intel)),
+            "MOV WORD PTR [BP-2H], 10H"
+        );
+
+        assert_eq!(render(&[0xb0, 0xff], hex(Syntax::Masm)), "mov al, 0ffh");
+        assert_eq!(
+            render(&[0xa1, 0x00, 0x01], hex(Syntax::Masm)),
+            "mov ax, word ptr ds:[100h]"
+        );
+        assert_eq!(
+            render(&[0xea, 0x78, 0x56, 0x34, 0x12], hex(Syntax::Nasm)),
+            "jmp 0x1234:0x5678"
+        );
+        assert_eq!(
+            render(&[0x26, 0xff, 0x1e, 0x00, 0x01], hex(Syntax::Masm)),
+            "call dword ptr es:[100h]"
+        );
+        assert_eq!(
+            render(&[0xe9, 0xfd, 0x00], hex(Syntax::Masm)),
+            "jmp near ptr $+100h"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use std::fmt::{self, Write};
+
+use super::*;
+
+/// Renders AT&T syntax: source operand first, `%` registers, `$` immediates
+/// and a size suffix when no register operand implies one.
+pub(super) fn write_instruction(
+    w: &mut impl Write,
+    instruction: &Instruction,
+    options: FormatOptions,
+) -> fmt::Result {
+    let op = instruction.op;
+    let operands: Vec<Operand> = instruction.operands.iter().flatten().copied().collect();
+    let far = operands.iter().any(|operand| match operand {
+        Operand::Pointer { .. } => true,
+        Operand::Memory(mem) => mem.size == MemoryOperandSize::Far,
+        _ => false,
+    });
+
+    let mut head = String::new();
+    if instruction.prefixes.lock {
+        head.push_str("lock ");
+    }
+    if let Some(segment) = instruction.prefixes.segment {
+        if !operands
+            .iter()
+            .any(|operand| matches!(operand, Operand::Memory(_)))
+        {
+            write!(head, "{segment} ")?;
+        }
+    }
+    match instruction.prefixes.rep {
+        Some(Rep::Rep) if matches!(op, Op::Cmpsb | Op::Cmpsw | Op::Scasb | Op::Scasw) => {
+            head.push_str("repe ")
+        }
+        Some(Rep::Rep) => head.push_str("rep "),
+        Some(Rep::Repne) => head.push_str("repne "),
+        None => {}
+    }
+    let prefixed = !head.is_empty();
+
+    match op {
+        Op::Cbw => head.push_str("cbtw"),
+        Op::Cwd => head.push_str("cwtd"),
+        Op::Retf => head.push_str("lret"),
+        Op::Db => head.push_str(".byte"),
+        Op::Jmp if far => head.push_str("ljmp"),
+        Op::Call if far => head.push_str("lcall"),
+        _ => head.push_str(mnemonic(op)),
+    }
+    if let Some(suffix) = size_suffix(op, &operands) {
+        head.push(suffix);
+    }
+
+    let operands: Vec<Operand> = match op {
+        // Shift by one is written without a count.
+        Op::Shl | Op::Shr | Op::Sar | Op::Rol | Op::Ror | Op::Rcl | Op::Rcr => match operands[..] {
+            [dest, Operand::Immediate(_)] => vec![dest],
+            [dest, count] => vec![count, dest],
+            _ => unreachable!(),
+        },
+        _ => operands.into_iter().rev().collect(),
+    };
+    if operands.is_empty() {
+        return write!(w, "{head}");
+    }
+    if prefixed {
+        write!(w, "{head} ")?;
+    } else {
+        write!(w, "{head:<6} ")?;
+    }
+
+    let word_destination = match operands.last() {
+        Some(Operand::Register(reg)) => reg.width() == RegisterWidth::Word,
+        Some(Operand::Memory(mem)) => mem.size == MemoryOperandSize::Word,
+        _ => false,
+    };
+    for (i, operand) in operands.into_iter().enumerate() {
+        if i > 0 {
+            write!(w, ",")?;
+        }
+        match operand {
+            Operand::Immediate(imm) if is_relative_branch(op) => {
+                let target = immediate_value(imm, true) + instruction.length as i32;
+                write!(w, ".{}", sign(target))?;
+                write_number(w, target.abs(), options)?;
+            }
+            // 83h sign-extends its byte immediate to the word destination.
+            Operand::Immediate(Immediate::Bit8(value)) if word_destination && is_alu(op) => {
+                write!(w, "$")?;
+                write_number(w, value as i8 as i32, options)?;
+            }
+            Operand::Immediate(imm) if op == Op::Db => {
+                write_number(w, immediate_value(imm, false), options)?;
+            }
+            Operand::Immediate(imm) => {
+                write!(w, "$")?;
+                write_number(w, immediate_value(imm, options.signed), options)?;
+            }
+            Operand::Pointer { segment, offset } => {
+                write!(w, "$")?;
+                write_number(w, segment as i32, options)?;
+                write!(w, ",$")?;
+                write_number(w, offset as i32, options)?;
+            }
+            Operand::Register(Register::DX) if matches!(op, Op::In | Op::Out) => {
+                write!(w, "(%dx)")?
+            }
+            Operand::Register(_) | Operand::Memory(_) => {
+                // Indirect branch targets are marked with a star.
+                if matches!(op, Op::Jmp | Op::Call) {
+                    write!(w, "*")?;
+                }
+                write_operand(w, operand, options)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_operand(w: &mut impl Write, operand: Operand, options: FormatOptions) -> fmt::Result {
+    match operand {
+        Operand::Register(reg) => write!(w, "%{}", register_name(reg)),
+        Operand::Memory(mem) => {
+            if let Some(segment) = mem.segment {
+                write!(w, "%{segment}:")?;
+            }
+            match address_parts(mem.kind) {
+                (None, Some(addr)) => write_number(w, addr, options),
+                (base, disp) => {
+                    if let Some(disp) = disp {
+                        write_number(w, disp, options)?;
+                    }
+                    let base = base.expect("memory operand must have a base");
+                    write!(w, "(%{})", base.replace('+', ",%"))
+                }
+            }
+        }
+        Operand::Immediate(_) | Operand::Pointer { .. } => unreachable!(),
+    }
+}
+
+/// `b` or `w` when the operand size is not implied by a register.
+fn size_suffix(op: Op, operands: &[Operand]) -> Option<char> {
+    if matches!(op, Op::Jmp | Op::Call) {
+        return None;
+    }
+
+    let mut memory = None;
+    for (i, operand) in operands.iter().enumerate() {
+        match operand {
+            // A CL shift count says nothing about the operand size.
+            Operand::Register(Register::CL)
+                if i == 1
+                    && matches!(
+                        op,
+                        Op::Shl | Op::Shr | Op::Sar | Op::Rol | Op::Ror | Op::Rcl | Op::Rcr
+                    ) => {}
+            Operand::Register(_) => return None,
+            Operand::Memory(mem) => memory = Some(mem.size),
+            _ => {}
+        }
+    }
+
+    match memory? {
+        MemoryOperandSize::Byte => Some('b'),
+        MemoryOperandSize::Word => Some('w'),
+        MemoryOperandSize::Far => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_objdump() {
+        let options = FormatOptions {
+            syntax: Syntax::Att,
+            radix: Radix::Hex,
+            signed: false,
+        };
+        // Output of `objdump -D -b binary -m i8086`.
+        let cases: &[(&[u8], &str)] = &[
+            (&[0x89, 0xd8], "mov    %bx,%ax"),
+            (&[0xc7, 0x46, 0xfe, 0x10, 0x00], "movw   $0x10,-0x2(%bp)"),
+            (&[0xd1, 0xe0], "shl    %ax"),
+            (&[0xd1, 0x27], "shlw   (%bx)"),
+            (&[0xd3, 0xe0], "shl    %cl,%ax"),
+            (&[0xd3, 0x27], "shlw   %cl,(%bx)"),
+            (&[0xff, 0xe3], "jmp    *%bx"),
+            (&[0xff, 0x27], "jmp    *(%bx)"),
+            (&[0xff, 0x2f], "ljmp   *(%bx)"),
+            (&[0xea, 0x78, 0x56, 0x34, 0x12], "ljmp   $0x1234,$0x5678"),
+            (&[0xc2, 0x04, 0x00], "ret    $0x4"),
+            (&[0xcb], "lret"),
+            (&[0x26, 0x8b, 0x07], "mov    %es:(%bx),%ax"),
+            (&[0xa1, 0x00, 0x01], "mov    0x100,%ax"),
+            (&[0x98], "cbtw"),
+            (&[0x99], "cwtd"),
+            (&[0xe4, 0x60], "in     $0x60,%al"),
+            (&[0xec], "in     (%dx),%al"),
+            (&[0xee], "out    %al,(%dx)"),
+            (&[0xcd, 0x21], "int    $0x21"),
+            (&[0xd4, 0x10], "aam    $0x10"),
+            (&[0x8d, 0x40, 0x04], "lea    0x4(%bx,%si),%ax"),
+            (&[0xf6, 0x07, 0x01], "testb  $0x1,(%bx)"),
+            (&[0x86, 0x07], "xchg   %al,(%bx)"),
+            (&[0xf0, 0x86, 0x07], "lock xchg %al,(%bx)"),
+            (&[0xfe, 0x07], "incb   (%bx)"),
+        ];
+        for (bytes, text) in cases {
+            let mut padded = [0; 16];
+            padded[..bytes.len()].copy_from_slice(bytes);
+            let instruction = decode_instruction(&padded);
+            assert_eq!(instruction.display(options).to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn relative_and_signed() {
+        let options = FormatOptions {
+            syntax: Syntax::Att,
+            ..Default::default()
+        };
+        let render = |bytes: &[u8]| {
+            let mut padded = [0; 16];
+            padded[..bytes.len()].copy_from_slice(bytes);
+            decode_instruction(&padded).display(options).to_string()
+        };
+        assert_eq!(render(&[0x75, 0xfc]), "jne    .-2");
+        assert_eq!(render(&[0x83, 0xc0, 0xff]), "add    $-1,%ax");
+        assert_eq!(render(&[0xf3, 0xa4]), "rep movsb");
+    }
+}
@@ -0,0 +1,175 @@
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::*;
+
+/// Devices reachable through the IN/OUT port address space.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IoBus {
+    pub pic: Pic,
+    pub pit: Pit,
+    /// Floppy served to INT 13h. While attached, the simulator handles the
+    /// interrupt itself instead of vectoring through the IVT.
+    pub disk: Option<Disk>,
+    /// CPU cycles elapsed since reset.
+    pub cycles: u64,
+    /// CPU cycles not yet turned into a PIT input clock.
+    pit_prescaler: u32,
+    /// Level last driven onto the timer IRQ line.
+    timer_line: bool,
+}
+
+impl IoBus {
+    /// PIT counter 0 output is wired to this PIC input.
+    pub const TIMER_IRQ: u8 = 0;
+
+    /// Advances time-driven devices by `cycles` CPU clock cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+        self.pit_prescaler += cycles;
+        while self.pit_prescaler >= Pit::CPU_CYCLES_PER_CLOCK {
+            self.pit_prescaler -= Pit::CPU_CYCLES_PER_CLOCK;
+            self.pit.clock();
+            self.update_timer_irq();
+        }
+    }
+
+    fn update_timer_irq(&mut self) {
+        let output = self.pit.channels[0].output;
+        if output != self.timer_line {
+            self.timer_line = output;
+            if output {
+                self.pic.raise_irq(Self::TIMER_IRQ);
+            } else {
+                self.pic.lower_irq(Self::TIMER_IRQ);
+            }
+        }
+    }
+
+    pub(crate) fn save(&self, w: &mut SnapshotWriter) {
+        w.u64(self.cycles);
+        self.pic.save(w);
+        for channel in &self.pit.channels {
+            channel.save(w);
+        }
+        w.u32(self.pit_prescaler);
+        w.bool(self.timer_line);
+        w.bool(self.disk.is_some());
+        if let Some(disk) = &self.disk {
+            disk.save(w);
+        }
+    }
+
+    pub(crate) fn restore(r: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let cycles = r.u64()?;
+        let pic = Pic::restore(r)?;
+        let pit = Pit {
+            channels: [
+                PitChannel::restore(r)?,
+                PitChannel::restore(r)?,
+                PitChannel::restore(r)?,
+            ],
+        };
+        let pit_prescaler = r.u32()?;
+        let timer_line = r.bool()?;
+        let disk = if r.bool()? {
+            Some(Disk::restore(r)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            pic,
+            pit,
+            disk,
+            cycles,
+            pit_prescaler,
+            timer_line,
+        })
+    }
+
+    /// Raises a hardware interrupt request line on the PIC.
+    pub fn raise_irq(&mut self, line: u8) {
+        self.pic.raise_irq(line);
+    }
+
+    /// Lowers a hardware interrupt request line on the PIC.
+    pub fn lower_irq(&mut self, line: u8) {
+        self.pic.lower_irq(line);
+    }
+
+    pub fn read8(&mut self, port: u16) -> u8 {
+        match port {
+            Pic::COMMAND_PORT | Pic::DATA_PORT => self.pic.read(port),
+            Pit::COUNTER0_PORT..=Pit::CONTROL_PORT => self.pit.read(port),
+            // Nothing decodes the port, the data bus floats high.
+            _ => 0xff,
+        }
+    }
+
+    pub fn write8(&mut self, port: u16, value: u8) {
+        match port {
+            Pic::COMMAND_PORT | Pic::DATA_PORT => self.pic.write(port, value),
+            Pit::COUNTER0_PORT..=Pit::CONTROL_PORT => {
+                self.pit.write(port, value);
+                self.update_timer_irq();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read16(&mut self, port: u16) -> u16 {
+        let lo = self.read8(port);
+        let hi = self.read8(port.wrapping_add(1));
+        u16::from_le_bytes([lo, hi])
+    }
+
+    pub fn write16(&mut self, port: u16, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+        self.write8(port, lo);
+        self.write8(port.wrapping_add(1), hi);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timer_raises_irq0() {
+        let mut io = IoBus::default();
+        // Counter 0, LSB/MSB, mode 2, count 10.
+        io.write8(Pit::CONTROL_PORT, 0b0011_0100);
+        io.write8(Pit::COUNTER0_PORT, 10);
+        io.write8(Pit::COUNTER0_PORT, 0);
+        // Setting the mode raised the output, and with it a request. Take
+        // it and end it the way an interrupt handler would.
+        assert_eq!(io.pic.acknowledge(), Some(0x08));
+        io.write8(Pic::COMMAND_PORT, 0x20);
+
+        // Load clock plus nine clocks until the output drops.
+        io.tick(10 * Pit::CPU_CYCLES_PER_CLOCK);
+        assert_eq!(io.pic.pending_irq(), None);
+        io.tick(Pit::CPU_CYCLES_PER_CLOCK - 1);
+        assert_eq!(io.pic.pending_irq(), None);
+        io.tick(1);
+        assert_eq!(io.pic.acknowledge(), Some(0x08));
+
+        // And again one period later.
+        io.write8(Pic::COMMAND_PORT, 0x20);
+        io.tick(9 * Pit::CPU_CYCLES_PER_CLOCK);
+        assert_eq!(io.pic.pending_irq(), None);
+        io.tick(Pit::CPU_CYCLES_PER_CLOCK);
+        assert_eq!(io.pic.pending_irq(), Some(0));
+    }
+
+    #[test]
+    fn counter_readable_through_ports() {
+        let mut io = IoBus::default();
+        io.write8(Pit::CONTROL_PORT, 0b0011_0000);
+        io.write8(Pit::COUNTER0_PORT, 0x10);
+        io.write8(Pit::COUNTER0_PORT, 0x00);
+        io.tick(5 * Pit::CPU_CYCLES_PER_CLOCK);
+
+        io.write8(Pit::CONTROL_PORT, 0b0000_0000);
+        assert_eq!(io.read8(Pit::COUNTER0_PORT), 0x0c);
+        assert_eq!(io.read8(Pit::COUNTER0_PORT), 0x00);
+    }
+}
@@ -1,12 +1,48 @@
 use std::fmt::{self, Display};
 
+mod bus;
+pub use bus::{Bus, MmioDevice};
+
 mod decoder;
-pub use decoder::decode_instruction;
+pub use decoder::{decode_instruction, MAX_INSTRUCTION_LENGTH};
+
+mod encoder;
+pub use encoder::encode_instruction;
 
 mod simulator;
-pub use simulator::simulate;
+pub use simulator::{accept_interrupt, simulate};
+
+mod disk;
+pub use disk::{load_boot_sector, Disk, SECTOR_SIZE};
+
+mod format;
+pub use format::{FormatOptions, Formatted, Radix, Syntax};
+
+mod io;
+pub use io::IoBus;
+
+mod loader;
+pub use loader::{build_psp, load_com, load_exe, LoadError, MAX_COMMAND_TAIL, MEMORY_TOP_SEGMENT};
+
+mod memory;
+pub use memory::{Access, AccessKind, Memory, Watchpoint, WriteProtect};
+
+mod pic;
+pub use pic::Pic;
+
+mod pit;
+pub use pit::{Pit, PitAccess, PitChannel};
 
-#[derive(Default, Debug, Clone, Copy)]
+mod recording;
+pub use recording::{MemoryWrite, Recording, Step};
+
+mod registers;
+pub use registers::RegisterWidth;
+
+mod snapshot;
+pub use snapshot::{Snapshot, SnapshotError};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RegisterFile {
     pub ax: u16,
     pub bx: u16,
@@ -18,13 +54,29 @@ pub struct RegisterFile {
     pub si: u16,
     pub di: u16,
 
+    pub cs: u16,
+    pub ds: u16,
+    pub es: u16,
+    pub ss: u16,
+
     pub ip: u16,
     pub flags: u16,
+
+    /// Set by STI; maskable interrupts are not recognized until the
+    /// following instruction has completed.
+    pub interrupt_shadow: bool,
 }
 
 impl RegisterFile {
-    const ZF_MASK: u16 = 1 << 3;
-    const SF_MASK: u16 = 1 << 4;
+    const CF_MASK: u16 = 1 << 0;
+    const PF_MASK: u16 = 1 << 2;
+    const AF_MASK: u16 = 1 << 4;
+    const ZF_MASK: u16 = 1 << 6;
+    const SF_MASK: u16 = 1 << 7;
+    const TF_MASK: u16 = 1 << 8;
+    const IF_MASK: u16 = 1 << 9;
+    const DF_MASK: u16 = 1 << 10;
+    const OF_MASK: u16 = 1 << 11;
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -32,46 +84,12 @@ pub struct Instruction {
     pub op: Op,
     pub length: u8,
     pub operands: [Option<Operand>; 2],
+    pub prefixes: Prefixes,
 }
 
 impl Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.op {
-            Op::Mov => write!(f, "mov")?,
-            Op::Add => write!(f, "add")?,
-            Op::Sub => write!(f, "sub")?,
-            Op::Cmp => write!(f, "cmp")?,
-            Op::Je => write!(f, "je")?,
-            Op::Jl => write!(f, "jl")?,
-            Op::Jle => write!(f, "jle")?,
-            Op::Jb => write!(f, "jb")?,
-            Op::Jbe => write!(f, "jba")?,
-            Op::Jp => write!(f, "jp")?,
-            Op::Jo => write!(f, "jo")?,
-            Op::Js => write!(f, "js")?,
-            Op::Jne => write!(f, "jne")?,
-            Op::Jnl => write!(f, "jnl")?,
-            Op::Jg => write!(f, "jg")?,
-            Op::Jnb => write!(f, "jnb")?,
-            Op::Ja => write!(f, "ja")?,
-            Op::Jnp => write!(f, "jnp")?,
-            Op::Jno => write!(f, "jno")?,
-            Op::Jns => write!(f, "jns")?,
-            Op::Loop => write!(f, "loop")?,
-            Op::Loopz => write!(f, "loopz")?,
-            Op::Loopnz => write!(f, "loopnz")?,
-            Op::Jcxz => write!(f, "jcxz")?,
-        };
-
-        if let Some(operand) = &self.operands[0] {
-            write!(f, " {operand}")?;
-        }
-
-        if let Some(operand) = &self.operands[1] {
-            write!(f, ", {operand}")?;
-        }
-
-        Ok(())
+        self.display(FormatOptions::default()).fmt(f)
     }
 }
 
@@ -101,6 +119,94 @@ pub enum Op {
     Loopz,
     Loopnz,
     Jcxz,
+    In,
+    Out,
+    Int,
+    Int3,
+    Into,
+    Iret,
+    Cli,
+    Sti,
+    Movsb,
+    Movsw,
+    Cmpsb,
+    Cmpsw,
+    Scasb,
+    Scasw,
+    Lodsb,
+    Lodsw,
+    Stosb,
+    Stosw,
+    Mul,
+    Imul,
+    Div,
+    Idiv,
+    Adc,
+    Sbb,
+    And,
+    Or,
+    Xor,
+    Test,
+    Not,
+    Neg,
+    Shl,
+    Shr,
+    Sar,
+    Rol,
+    Ror,
+    Rcl,
+    Rcr,
+    Daa,
+    Das,
+    Aaa,
+    Aas,
+    Aam,
+    Aad,
+    Inc,
+    Dec,
+    Xchg,
+    Lea,
+    Lds,
+    Les,
+    Cbw,
+    Cwd,
+    Xlat,
+    Lahf,
+    Sahf,
+    Jmp,
+    Call,
+    Ret,
+    Retf,
+    Clc,
+    Stc,
+    Cmc,
+    Cld,
+    Std,
+    Nop,
+    /// Waits for the TEST pin. Decoded as an instruction of its own rather
+    /// than a prefix since nothing here decodes the ESC opcodes it guards.
+    Wait,
+    /// A byte that starts no instruction decoded here, kept as data so a
+    /// disassembly can carry on past it. Its operand is the byte.
+    Db,
+}
+
+/// Prefix bytes that preceded the opcode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Prefixes {
+    pub rep: Option<Rep>,
+    /// F0: LOCK.
+    pub lock: bool,
+    /// 26/2E/36/3E: segment override.
+    pub segment: Option<SegmentRegister>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rep {
+    /// F3: REP, or REPE/REPZ for CMPS and SCAS.
+    Rep,
+    /// F2: REPNE/REPNZ.
+    Repne,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -108,80 +214,16 @@ pub enum Operand {
     Register(Register),
     Memory(MemoryOperand),
     Immediate(Immediate),
+    /// Direct far jump or call target.
+    Pointer {
+        segment: u16,
+        offset: u16,
+    },
 }
 
 impl Display for Operand {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Operand::Register(reg) => match reg {
-                Register::AL => write!(f, "al")?,
-                Register::CL => write!(f, "cl")?,
-                Register::DL => write!(f, "dl")?,
-                Register::BL => write!(f, "bl")?,
-                Register::AH => write!(f, "ah")?,
-                Register::CH => write!(f, "ch")?,
-                Register::DH => write!(f, "dh")?,
-                Register::BH => write!(f, "bh")?,
-
-                Register::AX => write!(f, "ax")?,
-                Register::CX => write!(f, "cx")?,
-                Register::DX => write!(f, "dx")?,
-                Register::BX => write!(f, "bx")?,
-                Register::SP => write!(f, "sp")?,
-                Register::BP => write!(f, "bp")?,
-                Register::SI => write!(f, "si")?,
-                Register::DI => write!(f, "di")?,
-            },
-            Operand::Memory(mem) => match mem.kind {
-                MemoryOperandKind::Direct_BX_SI => write!(f, "{} [bx + si]", mem.size)?,
-                MemoryOperandKind::Direct_BX_DI => write!(f, "{} [bx + di]", mem.size)?,
-                MemoryOperandKind::Direct_BP_SI => write!(f, "{} [bp + si]", mem.size)?,
-                MemoryOperandKind::Direct_BP_DI => write!(f, "{} [bp + di]", mem.size)?,
-                MemoryOperandKind::Direct_SI => write!(f, "{} [si]", mem.size)?,
-                MemoryOperandKind::Direct_DI => write!(f, "{} [di]", mem.size)?,
-                MemoryOperandKind::Direct_Address(value) => write!(f, "{} [{value}]", mem.size)?,
-                MemoryOperandKind::Direct_BX => write!(f, "{} [bx]", mem.size)?,
-
-                MemoryOperandKind::Disp8_BX_SI(disp) => {
-                    write!(f, "{} [bx + si {:+}]", mem.size, disp)?
-                }
-                MemoryOperandKind::Disp8_BX_DI(disp) => {
-                    write!(f, "{} [bx + di {:+}]", mem.size, disp)?
-                }
-                MemoryOperandKind::Disp8_BP_SI(disp) => {
-                    write!(f, "{} [bp + si {:+}]", mem.size, disp)?
-                }
-                MemoryOperandKind::Disp8_BP_DI(disp) => {
-                    write!(f, "{} [bp + di {:+}]", mem.size, disp)?
-                }
-                MemoryOperandKind::Disp8_SI(disp) => write!(f, "{} [si {:+}]", mem.size, disp)?,
-                MemoryOperandKind::Disp8_DI(disp) => write!(f, "{} [di {:+}]", mem.size, disp)?,
-                MemoryOperandKind::Disp8_BP(disp) => write!(f, "{} [bp {:+}]", mem.size, disp)?,
-                MemoryOperandKind::Disp8_BX(disp) => write!(f, "{} [bx {:+}]", mem.size, disp)?,
-
-                MemoryOperandKind::Disp16_BX_SI(disp) => {
-                    write!(f, "{} [bx + si {:+}]", mem.size, disp)?
-                }
-                MemoryOperandKind::Disp16_BX_DI(disp) => {
-                    write!(f, "{} [bx + di {:+}]", mem.size, disp)?
-                }
-                MemoryOperandKind::Disp16_BP_SI(disp) => {
-                    write!(f, "{} [bp + si {:+}]", mem.size, disp)?
-                }
-                MemoryOperandKind::Disp16_BP_DI(disp) => {
-                    write!(f, "{} [bp + di {:+}]", mem.size, disp)?
-                }
-                MemoryOperandKind::Disp16_SI(disp) => write!(f, "{} [si {:+}]", mem.size, disp)?,
-                MemoryOperandKind::Disp16_DI(disp) => write!(f, "{} [di {:+}]", mem.size, disp)?,
-                MemoryOperandKind::Disp16_BP(disp) => write!(f, "{} [bp {:+}]", mem.size, disp)?,
-                MemoryOperandKind::Disp16_BX(disp) => write!(f, "{} [bx {:+}]", mem.size, disp)?,
-            },
-            Operand::Immediate(imm) => match imm {
-                Immediate::Bit8(imm) => write!(f, "byte {}", imm)?,
-                Immediate::Bit16(imm) => write!(f, "word {}", imm)?,
-            },
-        };
-        Ok(())
+        format::write_operand(f, *self, FormatOptions::default())
     }
 }
 
@@ -206,6 +248,25 @@ pub enum Register {
     DI,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentRegister {
+    ES,
+    CS,
+    SS,
+    DS,
+}
+
+impl Display for SegmentRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ES => write!(f, "es"),
+            Self::CS => write!(f, "cs"),
+            Self::SS => write!(f, "ss"),
+            Self::DS => write!(f, "ds"),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Immediate {
     Bit8(u8),
@@ -216,12 +277,16 @@ pub enum Immediate {
 pub struct MemoryOperand {
     kind: MemoryOperandKind,
     size: MemoryOperandSize,
+    /// Segment override, if any; otherwise DS, or SS for BP based addressing.
+    segment: Option<SegmentRegister>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MemoryOperandSize {
     Word,
     Byte,
+    /// Segment and offset pair read by far indirect jumps and calls.
+    Far,
 }
 
 impl MemoryOperandSize {
@@ -239,6 +304,7 @@ impl Display for MemoryOperandSize {
         match self {
             Self::Byte => write!(f, "byte"),
             Self::Word => write!(f, "word"),
+            Self::Far => write!(f, "far"),
         }
     }
 }
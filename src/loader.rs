@@ -0,0 +1,345 @@
+use std::fmt::{self, Display};
+
+use crate::bus::ADDRESS_MASK;
+use crate::memory::{read_u16, write_u16};
+use crate::simulator::physical_address;
+use crate::*;
+
+/// First segment past conventional memory, recorded in the PSP as the end of
+/// the program's allocation.
+pub const MEMORY_TOP_SEGMENT: u16 = 0xa000;
+
+/// The command tail at 80h holds a length byte, up to 126 characters and
+/// the terminating carriage return.
+pub const MAX_COMMAND_TAIL: usize = 126;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    /// The image does not start with an `MZ` signature.
+    NotAnExe,
+    /// The header or relocation table points past the end of the file.
+    Truncated,
+    /// The image does not fit between the load segment and the top of memory,
+    /// or a .COM image is larger than its segment allows.
+    TooLarge,
+    /// There is no room below the load segment for the PSP.
+    BadLoadSegment,
+    /// A relocation points past the end of memory.
+    BadRelocation,
+    CommandTailTooLong,
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAnExe => write!(f, "missing MZ signature"),
+            Self::Truncated => write!(f, "executable is truncated"),
+            Self::TooLarge => write!(f, "image does not fit in conventional memory"),
+            Self::BadLoadSegment => write!(f, "no room for the PSP below the load segment"),
+            Self::BadRelocation => write!(f, "relocation points past the end of memory"),
+            Self::CommandTailTooLong => write!(f, "command tail longer than 126 bytes"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Loads an MZ executable with its load module at `load_segment:0000` and
+/// the PSP in the 100h bytes right below it.
+///
+/// Relocations are applied against `load_segment`. Returns the initial
+/// register state: CS:IP and SS:SP from the header, DS and ES pointing at
+/// the PSP.
+pub fn load_exe(
+    image: &[u8],
+    memory: &mut [u8],
+    load_segment: u16,
+    command_tail: &[u8],
+) -> Result<RegisterFile, LoadError> {
+    let word = |offset: usize| -> Result<u16, LoadError> {
+        image
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .ok_or(LoadError::Truncated)
+    };
+
+    if !image.starts_with(b"MZ") && !image.starts_with(b"ZM") {
+        return Err(LoadError::NotAnExe);
+    }
+    let last_page_bytes = word(0x02)? as usize;
+    let pages = word(0x04)? as usize;
+    let relocation_count = word(0x06)? as usize;
+    let header_size = word(0x08)? as usize * 16;
+    let ss = word(0x0e)?;
+    let sp = word(0x10)?;
+    let ip = word(0x14)?;
+    let cs = word(0x16)?;
+    let relocation_table = word(0x18)? as usize;
+
+    // The page count includes the header; a non-zero last page count says
+    // how much of the final page is used.
+    let mut file_size = pages * 512;
+    if last_page_bytes != 0 {
+        file_size = file_size.saturating_sub(512 - last_page_bytes);
+    }
+    let module = image
+        .get(header_size..file_size)
+        .ok_or(LoadError::Truncated)?;
+
+    let psp_segment = load_segment
+        .checked_sub(0x10)
+        .ok_or(LoadError::BadLoadSegment)?;
+    let start = physical_address(load_segment, 0);
+    let end = start + module.len();
+    if end > (MEMORY_TOP_SEGMENT as usize) << 4 || end > memory.len() {
+        return Err(LoadError::TooLarge);
+    }
+    memory[start..end].copy_from_slice(module);
+
+    for i in 0..relocation_count {
+        let entry = relocation_table + i * 4;
+        let offset = word(entry)?;
+        let segment = word(entry + 2)?.wrapping_add(load_segment);
+        let addr = physical_address(segment, offset);
+        // Memory may stop short of 1 MiB, and the header is not to be trusted.
+        if addr.max((addr + 1) & ADDRESS_MASK) >= memory.len() {
+            return Err(LoadError::BadRelocation);
+        }
+        let value = read_u16(memory, addr).wrapping_add(load_segment);
+        write_u16(memory, addr, value);
+    }
+
+    build_psp(memory, psp_segment, command_tail)?;
+
+    Ok(RegisterFile {
+        cs: cs.wrapping_add(load_segment),
+        ip,
+        ss: ss.wrapping_add(load_segment),
+        sp,
+        ds: psp_segment,
+        es: psp_segment,
+        flags: RegisterFile::IF_MASK,
+        ..Default::default()
+    })
+}
+
+/// Loads a .COM image at `segment:0100` with the PSP at `segment:0000`.
+///
+/// All segment registers point at the PSP, IP is 100h and SP is FFFEh with a
+/// zero word on the stack, so a near RET lands on the INT 20h at PSP:0000.
+pub fn load_com(
+    image: &[u8],
+    memory: &mut [u8],
+    segment: u16,
+    command_tail: &[u8],
+) -> Result<RegisterFile, LoadError> {
+    // The PSP and the return word take their share of the 64K segment.
+    if image.len() > 0x10000 - 0x100 - 2 {
+        return Err(LoadError::TooLarge);
+    }
+    let start = physical_address(segment, 0x100);
+    let end = physical_address(segment, 0) + 0x10000;
+    if end > (MEMORY_TOP_SEGMENT as usize) << 4 || end > memory.len() {
+        return Err(LoadError::TooLarge);
+    }
+    memory[start..start + image.len()].copy_from_slice(image);
+
+    build_psp(memory, segment, command_tail)?;
+    write_u16(memory, physical_address(segment, 0xfffe), 0x0000);
+
+    Ok(RegisterFile {
+        cs: segment,
+        ds: segment,
+        es: segment,
+        ss: segment,
+        ip: 0x100,
+        sp: 0xfffe,
+        flags: RegisterFile::IF_MASK,
+        ..Default::default()
+    })
+}
+
+/// Writes a program segment prefix at `segment:0000`.
+pub fn build_psp(memory: &mut [u8], segment: u16, command_tail: &[u8]) -> Result<(), LoadError> {
+    if command_tail.len() > MAX_COMMAND_TAIL {
+        return Err(LoadError::CommandTailTooLong);
+    }
+
+    let base = physical_address(segment, 0);
+    let psp = &mut memory[base..base + 0x100];
+    psp.fill(0);
+
+    // INT 20h, so a RET to offset 0 terminates the program.
+    psp[0x00..0x02].copy_from_slice(&[0xcd, 0x20]);
+    psp[0x02..0x04].copy_from_slice(&MEMORY_TOP_SEGMENT.to_le_bytes());
+    // INT 21h / RETF dispatcher.
+    psp[0x50..0x53].copy_from_slice(&[0xcd, 0x21, 0xcb]);
+    // Unopened FCBs: no drive, blank file name.
+    psp[0x5d..0x68].fill(b' ');
+    psp[0x6d..0x78].fill(b' ');
+
+    psp[0x80] = command_tail.len() as u8;
+    psp[0x81..0x81 + command_tail.len()].copy_from_slice(command_tail);
+    psp[0x81 + command_tail.len()] = b'\r';
+
+    // Terminate, Ctrl-Break and critical error vectors as they were at load.
+    for (i, vector) in [0x22, 0x23, 0x24].into_iter().enumerate() {
+        let entry = vector * 4;
+        let saved = base + 0x0a + i * 4;
+        memory.copy_within(entry..entry + 4, saved);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A three paragraph header with two relocations, at 0000:0001 and
+    /// 0001:0000, followed by a 20h byte load module.
+    fn sample_exe() -> Vec<u8> {
+        let mut image = vec![0; 0x50];
+        let header: [u16; 13] = [
+            u16::from_le_bytes(*b"MZ"),
+            0x50,   // bytes in last page
+            1,      // pages
+            2,      // relocations
+            3,      // header paragraphs
+            0,      // min alloc
+            0xffff, // max alloc
+            0x0001, // ss
+            0x0010, // sp
+            0,      // checksum
+            0x0004, // ip
+            0x0000, // cs
+            0x001c, // relocation table
+        ];
+        for (i, value) in header.into_iter().enumerate() {
+            image[i * 2..i * 2 + 2].copy_from_slice(&value.to_le_bytes());
+        }
+        image[0x1c..0x24].copy_from_slice(&[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00]);
+
+        // mov ax, 1 at the start, a segment word at paragraph 1.
+        image[0x30..0x33].copy_from_slice(&[0xb8, 0x01, 0x00]);
+        image[0x40..0x42].copy_from_slice(&[0x02, 0x00]);
+        image
+    }
+
+    #[test]
+    fn relocates_and_sets_entry() {
+        let mut memory = vec![0; 1024 * 1024];
+        let registers = load_exe(&sample_exe(), &mut memory, 0x1010, b" /x").unwrap();
+
+        assert_eq!((registers.cs, registers.ip), (0x1010, 0x0004));
+        assert_eq!((registers.ss, registers.sp), (0x1011, 0x0010));
+        assert_eq!((registers.ds, registers.es), (0x1000, 0x1000));
+
+        // Both fixups had the load segment added.
+        assert_eq!(&memory[0x10100..0x10103], &[0xb8, 0x11, 0x10]);
+        assert_eq!(&memory[0x10110..0x10112], &[0x12, 0x10]);
+    }
+
+    #[test]
+    fn psp_layout() {
+        let mut memory = vec![0; 1024 * 1024];
+        memory[0x88..0x8c].copy_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+        load_exe(&sample_exe(), &mut memory, 0x1010, b" /x").unwrap();
+
+        let psp = &memory[0x10000..0x10100];
+        assert_eq!(&psp[0x00..0x04], &[0xcd, 0x20, 0x00, 0xa0]);
+        assert_eq!(&psp[0x0a..0x0e], &[0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(&psp[0x50..0x53], &[0xcd, 0x21, 0xcb]);
+        assert_eq!(&psp[0x80..0x85], b"\x03 /x\r");
+    }
+
+    #[test]
+    fn rejects_bad_images() {
+        let mut memory = vec![0; 1024 * 1024];
+        assert_eq!(
+            load_exe(b"not an exe", &mut memory, 0x1010, b"").unwrap_err(),
+            LoadError::NotAnExe
+        );
+
+        let mut image = sample_exe();
+        image.truncate(0x40);
+        assert_eq!(
+            load_exe(&image, &mut memory, 0x1010, b"").unwrap_err(),
+            LoadError::Truncated
+        );
+
+        assert_eq!(
+            load_exe(&sample_exe(), &mut memory, 0x0008, b"").unwrap_err(),
+            LoadError::BadLoadSegment
+        );
+        assert_eq!(
+            load_exe(&sample_exe(), &mut memory, 0x1010, &[b'a'; 127]).unwrap_err(),
+            LoadError::CommandTailTooLong
+        );
+    }
+
+    #[test]
+    fn rejects_relocations_past_memory() {
+        // The second fixup lands at 2011:0000, past the end of 128K.
+        let mut image = sample_exe();
+        image[0x22..0x24].copy_from_slice(&0x1001u16.to_le_bytes());
+        let mut memory = vec![0; 0x20000];
+        assert_eq!(
+            load_exe(&image, &mut memory, 0x1010, b"").unwrap_err(),
+            LoadError::BadRelocation
+        );
+    }
+
+    #[test]
+    fn com_initial_state() {
+        let mut memory = vec![0; 1024 * 1024];
+        // mov ah, 4ch / int 21h
+        let image = [0xb4, 0x4c, 0xcd, 0x21];
+        memory[0x2fffe] = 0xaa;
+        let registers = load_com(&image, &mut memory, 0x2000, b" hello").unwrap();
+
+        assert_eq!(
+            (registers.cs, registers.ds, registers.es, registers.ss),
+            (0x2000, 0x2000, 0x2000, 0x2000)
+        );
+        assert_eq!((registers.ip, registers.sp), (0x0100, 0xfffe));
+        assert_eq!(&memory[0x20100..0x20104], &image);
+        assert_eq!(&memory[0x2fffe..0x30000], &[0x00, 0x00]);
+        assert_eq!(&memory[0x20000..0x20002], &[0xcd, 0x20]);
+        assert_eq!(&memory[0x20080..0x20088], b"\x06 hello\r");
+    }
+
+    #[test]
+    fn com_returns_through_psp() {
+        let mut memory = Memory::default();
+        let mut registers = load_com(&[0xc3], &mut memory, 0x2000, b"").unwrap();
+
+        let instruction = decode_instruction(&memory[0x20100..0x20110]);
+        simulate(
+            &mut registers,
+            &mut memory,
+            &mut IoBus::default(),
+            instruction,
+        );
+        assert_eq!(
+            (registers.cs, registers.ip, registers.sp),
+            (0x2000, 0x0000, 0x0000)
+        );
+        let instruction = decode_instruction(&memory[0x20000..0x20010]);
+        assert_eq!(instruction.to_string(), "int byte 32");
+    }
+
+    #[test]
+    fn com_too_large() {
+        let mut memory = vec![0; 1024 * 1024];
+        let image = vec![0x90; 0xff00];
+        assert_eq!(
+            load_com(&image, &mut memory, 0x2000, b"").unwrap_err(),
+            LoadError::TooLarge
+        );
+        assert_eq!(
+            load_com(&[0x90], &mut memory, 0x9100, b"").unwrap_err(),
+            LoadError::TooLarge
+        );
+    }
+}
@@ -6,7 +6,18 @@ use std::{
 
 use r8086::*;
 
+/// Instructions executed before a booted image is stopped.
+const BOOT_STEP_LIMIT: usize = 1_000_000;
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, image_path] = &args[..] {
+        if flag == "--boot" {
+            boot(image_path);
+            return;
+        }
+    }
+
     let input_asm_file_path = "input/program.asm";
     let input_bin_file_path = "input/program.bin";
     let output_asm_file_path = "output/disassembly.asm";
@@ -33,7 +44,8 @@ fn main() {
     let mut input_bin_file = File::open(input_bin_file_path).unwrap();
 
     let mut register_file = RegisterFile::default();
-    let mut memory = vec![0; 1024 * 1024];
+    let mut io = IoBus::default();
+    let mut memory = Memory::default();
     let program_size = input_bin_file.read(&mut memory[..]).unwrap() as u16;
     dbg!(&memory[0..program_size as _]);
     dbg!(program_size);
@@ -42,9 +54,9 @@ fn main() {
 
     let mut offset = 0;
     while offset < program_size {
-        let end = (offset + 6).clamp(0, program_size);
+        let end = (offset + 16).clamp(0, program_size);
         let bytes = &memory[offset as usize..end as usize];
-        let padded = &mut [0; 6];
+        let padded = &mut [0; 16];
         padded[0..bytes.len()].copy_from_slice(bytes);
         let instruction = decode_instruction(padded);
         offset += instruction.length as u16;
@@ -52,26 +64,49 @@ fn main() {
     }
 
     while register_file.ip < program_size {
-        let end = (6 + register_file.ip).clamp(0, program_size);
-        let bytes = &memory[register_file.ip as usize..end as usize];
-        let padded = &mut [0; 6];
-        padded[0..bytes.len()].copy_from_slice(bytes);
+        accept_interrupt(&mut register_file, &mut memory, &mut io);
 
-        let instruction = decode_instruction(padded);
-        simulate(&mut register_file, &mut memory, instruction);
+        let instruction = fetch(&mut memory, &register_file);
+        let cycles = simulate(&mut register_file, &mut memory, &mut io, instruction);
+        io.tick(cycles);
     }
 
-    writeln!(register_output, "ax: {:#06x}", register_file.ax).unwrap();
-    writeln!(register_output, "bx: {:#06x}", register_file.bx).unwrap();
-    writeln!(register_output, "cx: {:#06x}", register_file.cx).unwrap();
-    writeln!(register_output, "dx: {:#06x}", register_file.dx).unwrap();
-    writeln!(register_output, "sp: {:#06x}", register_file.sp).unwrap();
-    writeln!(register_output, "bp: {:#06x}", register_file.bp).unwrap();
-    writeln!(register_output, "si: {:#06x}", register_file.si).unwrap();
-    writeln!(register_output, "di: {:#06x}", register_file.di).unwrap();
-    writeln!(register_output, "flags: {:016b}", register_file.flags).unwrap();
-    writeln!(register_output, "ip: {}", register_file.ip).unwrap();
-
+    register_file.write_report(&mut register_output).unwrap();
     memory_dump.write_all(&memory).unwrap();
 }
 
+/// Boots sector 0 of a raw floppy image at 0000:7C00 with the image serving
+/// INT 13h as drive 0.
+fn boot(image_path: &str) {
+    let image = std::fs::read(image_path).expect("Failed to read the disk image.");
+    let disk = Disk::new(image, 0);
+
+    let mut memory = Memory::default();
+    let mut register_file = load_boot_sector(&disk, &mut memory).expect("No boot sector.");
+    let mut io = IoBus::default();
+    io.disk = Some(disk);
+
+    for _ in 0..BOOT_STEP_LIMIT {
+        accept_interrupt(&mut register_file, &mut memory, &mut io);
+
+        let instruction = fetch(&mut memory, &register_file);
+        let cycles = simulate(&mut register_file, &mut memory, &mut io, instruction);
+        io.tick(cycles);
+    }
+
+    let mut register_output = std::fs::File::create("output/register.txt").unwrap();
+    register_file.write_report(&mut register_output).unwrap();
+    std::fs::write("output/memory.dump", &memory[..]).unwrap();
+}
+
+/// Reads the instruction at CS:IP through the bus, so ROM and devices mapped
+/// over memory supply code the same way they supply data. IP wraps within
+/// the segment and the address at 1 MiB.
+fn fetch(memory: &mut Memory, register_file: &RegisterFile) -> Instruction {
+    let mut window = [0; 16];
+    for (i, byte) in window.iter_mut().enumerate() {
+        let offset = register_file.ip.wrapping_add(i as u16) as usize;
+        *byte = memory.read8((((register_file.cs as usize) << 4) + offset) & 0xfffff);
+    }
+    decode_instruction(&window)
+}
@@ -0,0 +1,488 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut, Range};
+
+use crate::bus::ADDRESS_MASK;
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A memory access made by the simulator, tagged with the instruction that
+/// made it. Word accesses are a single entry of size 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub cs: u16,
+    pub ip: u16,
+    pub address: usize,
+    pub size: u8,
+    pub kind: AccessKind,
+    pub value: u16,
+}
+
+/// Stops on accesses of the given kinds touching any byte of `range`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: Range<usize>,
+    pub read: bool,
+    pub write: bool,
+}
+
+/// What happens to a write into a read-only range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteProtect {
+    /// The write is dropped, as on real hardware.
+    Ignore,
+    /// The write is dropped and reported by [`Memory::take_faults`].
+    Report,
+}
+
+enum Mapping {
+    /// Backed by the RAM array, but writes don't reach it.
+    Rom(WriteProtect),
+    Device(Box<dyn MmioDevice>),
+}
+
+/// The default [`Bus`]: RAM with ROM and device ranges mapped over it.
+///
+/// A RAM array shorter than 1 MiB leaves the top of the address space
+/// unpopulated: it reads as FFh and ignores writes.
+///
+/// Every access through the bus checks watchpoints and feeds the optional
+/// access log. Dereferencing to the raw bytes is meant for loaders and dumps
+/// and bypasses watchpoints, the log and all mappings.
+pub struct Memory {
+    bytes: Vec<u8>,
+    mappings: Vec<(Range<usize>, Mapping)>,
+    watchpoints: Vec<Watchpoint>,
+    hits: Vec<Access>,
+    faults: Vec<Access>,
+    log: Option<Vec<Access>>,
+    /// Byte writes made while a [`Recording`] step runs.
+    journal: Option<Vec<MemoryWrite>>,
+    /// CS:IP of the instruction being executed.
+    cs: u16,
+    ip: u16,
+}
+
+impl Default for Memory {
+    /// A zeroed 1 MiB address space.
+    fn default() -> Self {
+        Self::from(vec![0; ADDRESS_MASK + 1])
+    }
+}
+
+impl From<Vec<u8>> for Memory {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            mappings: Vec::new(),
+            watchpoints: Vec::new(),
+            hits: Vec::new(),
+            faults: Vec::new(),
+            log: None,
+            journal: None,
+            cs: 0,
+            ip: 0,
+        }
+    }
+}
+
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mappings: Vec<_> = self.mappings.iter().map(|(range, _)| range).collect();
+        f.debug_struct("Memory")
+            .field("size", &self.bytes.len())
+            .field("mappings", &mappings)
+            .field("watchpoints", &self.watchpoints)
+            .field("logging", &self.log.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Deref for Memory {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl DerefMut for Memory {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl Bus for Memory {
+    fn read8(&mut self, address: usize) -> u8 {
+        let value = self.load(address);
+        self.access(address, 1, AccessKind::Read, value as u16);
+        value
+    }
+
+    fn read16(&mut self, address: usize) -> u16 {
+        let value =
+            u16::from_le_bytes([self.load(address), self.load((address + 1) & ADDRESS_MASK)]);
+        self.access(address, 2, AccessKind::Read, value);
+        value
+    }
+
+    fn write8(&mut self, address: usize, value: u8) {
+        self.store(address, value);
+        self.access(address, 1, AccessKind::Write, value as u16);
+    }
+
+    fn write16(&mut self, address: usize, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+        self.store(address, lo);
+        self.store((address + 1) & ADDRESS_MASK, hi);
+        self.access(address, 2, AccessKind::Write, value);
+    }
+
+    fn set_instruction(&mut self, cs: u16, ip: u16) {
+        self.cs = cs;
+        self.ip = ip;
+    }
+}
+
+impl Memory {
+    /// Copies `contents` to `start` and makes the range read-only. Whatever
+    /// runs past the end of RAM is dropped, as that part is unpopulated.
+    pub fn map_rom(&mut self, start: usize, contents: &[u8], protect: WriteProtect) {
+        let range = start..start + contents.len();
+        let end = range.end.min(self.bytes.len());
+        if start < end {
+            self.bytes[start..end].copy_from_slice(&contents[..end - start]);
+        }
+        self.protect(range, protect);
+    }
+
+    /// Makes `range` read-only, keeping what it holds. The BIOS area
+    /// F0000-FFFFF is the usual candidate.
+    pub fn protect(&mut self, range: Range<usize>, protect: WriteProtect) {
+        self.mappings.push((range, Mapping::Rom(protect)));
+    }
+
+    /// Hands every bus access in `range` to `device`. Later mappings take
+    /// precedence over earlier ones they overlap.
+    pub fn map_device(&mut self, range: Range<usize>, device: Box<dyn MmioDevice>) {
+        self.mappings.push((range, Mapping::Device(device)));
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// Returns the accesses that triggered a watchpoint since the last call.
+    pub fn take_hits(&mut self) -> Vec<Access> {
+        std::mem::take(&mut self.hits)
+    }
+
+    /// Returns the byte writes a [`WriteProtect::Report`] range refused since
+    /// the last call.
+    pub fn take_faults(&mut self) -> Vec<Access> {
+        std::mem::take(&mut self.faults)
+    }
+
+    /// Starts logging every access, discarding any earlier log.
+    pub fn enable_log(&mut self) {
+        self.log = Some(Vec::new());
+    }
+
+    /// Stops logging and returns what was logged.
+    pub fn disable_log(&mut self) -> Option<Vec<Access>> {
+        self.log.take()
+    }
+
+    pub fn log(&self) -> Option<&[Access]> {
+        self.log.as_deref()
+    }
+
+    /// Starts collecting every byte written through the bus.
+    pub(crate) fn start_journal(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    pub(crate) fn take_journal(&mut self) -> Vec<MemoryWrite> {
+        self.journal.take().unwrap_or_default()
+    }
+
+    fn mapping(&mut self, address: usize) -> Option<(usize, &mut Mapping)> {
+        self.mappings
+            .iter_mut()
+            .rev()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(range, mapping)| (address - range.start, mapping))
+    }
+
+    fn load(&mut self, address: usize) -> u8 {
+        match self.mapping(address) {
+            Some((offset, Mapping::Device(device))) => device.read8(offset),
+            _ => self.bytes.get(address).copied().unwrap_or(0xff),
+        }
+    }
+
+    fn store(&mut self, address: usize, value: u8) {
+        let old = self.bytes.get(address).copied().unwrap_or(0xff);
+        match self.mapping(address) {
+            Some((_, Mapping::Rom(WriteProtect::Ignore))) => {}
+            Some((_, Mapping::Rom(WriteProtect::Report))) => self.faults.push(Access {
+                cs: self.cs,
+                ip: self.ip,
+                address,
+                size: 1,
+                kind: AccessKind::Write,
+                value: value as u16,
+            }),
+            Some((offset, Mapping::Device(device))) => device.write8(offset, value),
+            None => {
+                if let Some(byte) = self.bytes.get_mut(address) {
+                    *byte = value;
+                }
+            }
+        }
+        if let Some(journal) = &mut self.journal {
+            let new = self.bytes.get(address).copied().unwrap_or(0xff);
+            journal.push(MemoryWrite { address, old, new });
+        }
+    }
+
+    fn access(&mut self, address: usize, size: u8, kind: AccessKind, value: u16) {
+        let access = Access {
+            cs: self.cs,
+            ip: self.ip,
+            address,
+            size,
+            kind,
+            value,
+        };
+        let last = (address + size as usize - 1) & ADDRESS_MASK;
+        let hit = self.watchpoints.iter().any(|watchpoint| {
+            let watched = match kind {
+                AccessKind::Read => watchpoint.read,
+                AccessKind::Write => watchpoint.write,
+            };
+            watched && (watchpoint.range.contains(&address) || watchpoint.range.contains(&last))
+        });
+        if hit {
+            self.hits.push(access);
+        }
+        if let Some(log) = &mut self.log {
+            log.push(access);
+        }
+    }
+}
+
+pub(crate) fn read_u16(memory: &[u8], addr: usize) -> u16 {
+    u16::from_le_bytes([memory[addr], memory[(addr + 1) & ADDRESS_MASK]])
+}
+
+pub(crate) fn write_u16(memory: &mut [u8], addr: usize, value: u16) {
+    let [lo, hi] = value.to_le_bytes();
+    memory[addr] = lo;
+    memory[(addr + 1) & ADDRESS_MASK] = hi;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchpoints_catch_simulator_accesses() {
+        let mut memory = Memory::default();
+        memory.add_watchpoint(Watchpoint {
+            range: 0x201..0x202,
+            read: false,
+            write: true,
+        });
+        let mut registers = RegisterFile {
+            ax: 0x1234,
+            ip: 0x10,
+            ..Default::default()
+        };
+        let mut io = IoBus::default();
+
+        // mov [0x200], ax touches 0x201 with its high byte.
+        let instruction = decode_instruction(&[0xa3, 0x00, 0x02, 0, 0, 0]);
+        simulate(&mut registers, &mut memory, &mut io, instruction);
+        assert_eq!(
+            memory.take_hits(),
+            vec![Access {
+                cs: 0,
+                ip: 0x10,
+                address: 0x200,
+                size: 2,
+                kind: AccessKind::Write,
+                value: 0x1234,
+            }]
+        );
+
+        // Reads don't trigger a write watchpoint, nor do bytes outside it.
+        let instruction = decode_instruction(&[0xa1, 0x00, 0x02, 0, 0, 0]);
+        simulate(&mut registers, &mut memory, &mut io, instruction);
+        let instruction = decode_instruction(&[0xa2, 0x02, 0x02, 0, 0, 0]);
+        simulate(&mut registers, &mut memory, &mut io, instruction);
+        assert!(memory.take_hits().is_empty());
+    }
+
+    #[test]
+    fn logs_every_access() {
+        let mut memory = Memory::default();
+        memory[0x400..0x404].copy_from_slice(&[0x00, 0x10, 0x00, 0x20]);
+        let mut registers = RegisterFile {
+            sp: 0x100,
+            ..Default::default()
+        };
+        let mut io = IoBus::default();
+        memory.enable_log();
+
+        // call far [0x400]: a far pointer read and two pushes.
+        let instruction = decode_instruction(&[0xff, 0x1e, 0x00, 0x04, 0, 0]);
+        simulate(&mut registers, &mut memory, &mut io, instruction);
+        let log = memory.disable_log().unwrap();
+
+        let accesses: Vec<_> = log
+            .iter()
+            .map(|access| (access.address, access.size, access.kind))
+            .collect();
+        assert_eq!(
+            accesses,
+            vec![
+                (0x400, 2, AccessKind::Read),
+                (0x402, 2, AccessKind::Read),
+                (0x0fe, 2, AccessKind::Write),
+                (0x0fc, 2, AccessKind::Write),
+            ]
+        );
+        assert!(log.iter().all(|access| access.ip == 0));
+        assert_eq!(memory.log(), None);
+    }
+
+    /// A latch that reads back the complement of what was last written.
+    struct Inverter(u8);
+
+    impl MmioDevice for Inverter {
+        fn read8(&mut self, _offset: usize) -> u8 {
+            !self.0
+        }
+
+        fn write8(&mut self, _offset: usize, value: u8) {
+            self.0 = value;
+        }
+    }
+
+    #[test]
+    fn rom_and_devices() {
+        let mut memory = Memory::default();
+        memory.map_rom(
+            0xffff0,
+            &[0xea, 0x5b, 0xe0, 0x00, 0xf0],
+            WriteProtect::Ignore,
+        );
+        memory.map_device(0xb8000..0xb8002, Box::new(Inverter(0)));
+
+        memory.write16(0xffff0, 0x9090);
+        assert_eq!(memory.read16(0xffff0), 0x5bea);
+        assert!(memory.take_faults().is_empty());
+
+        memory.write8(0xb8001, 0x0f);
+        assert_eq!(memory.read8(0xb8000), 0xf0);
+        assert_eq!(memory[0xb8001], 0);
+
+        // A word straddling the end of the device reaches both sides.
+        memory[0xb8002] = 0x12;
+        assert_eq!(memory.read16(0xb8001), 0x12f0);
+
+        // Code sees the same mappings.
+        let mut registers = RegisterFile::default();
+        // mov al, [bx]
+        let instruction = decode_instruction(&[0x8a, 0x07, 0, 0, 0, 0]);
+        registers.ds = 0xb000;
+        registers.bx = 0x8000;
+        simulate(
+            &mut registers,
+            &mut memory,
+            &mut IoBus::default(),
+            instruction,
+        );
+        assert_eq!(registers.ax & 0xff, 0xf0);
+    }
+
+    #[test]
+    fn rom_past_end_of_ram() {
+        let mut memory = Memory::from(vec![0; 0x10000]);
+        memory.map_rom(0xfffe, &[0x11, 0x22, 0x33, 0x44], WriteProtect::Ignore);
+        assert_eq!(&memory[0xfffe..], &[0x11, 0x22]);
+        assert_eq!(memory.read16(0xfffe), 0x2211);
+        assert_eq!(memory.read8(0x10000), 0xff);
+
+        // Entirely past the end is mapped, but holds nothing.
+        memory.map_rom(0x20000, &[0x55], WriteProtect::Ignore);
+        assert_eq!(memory.read8(0x20000), 0xff);
+    }
+
+    #[test]
+    fn reports_stray_writes() {
+        let mut memory = Memory::default();
+        memory[0xf0000] = 0xaa;
+        memory.protect(0xf0000..0x100000, WriteProtect::Report);
+        let mut registers = RegisterFile {
+            ax: 0x1234,
+            bx: 0x0fff,
+            ds: 0xef00,
+            ip: 0x40,
+            ..Default::default()
+        };
+
+        // mov [bx], ax: the low byte is the last byte of RAM, the high byte
+        // the first of the protected range.
+        let instruction = decode_instruction(&[0x89, 0x07, 0, 0, 0, 0]);
+        simulate(
+            &mut registers,
+            &mut memory,
+            &mut IoBus::default(),
+            instruction,
+        );
+        assert_eq!(
+            memory.take_faults(),
+            vec![Access {
+                cs: 0,
+                ip: 0x40,
+                address: 0xf0000,
+                size: 1,
+                kind: AccessKind::Write,
+                value: 0x12,
+            }]
+        );
+        assert_eq!(&memory[0xeffff..0xf0001], &[0x34, 0xaa]);
+
+        // An ignoring range drops the write without a report.
+        memory.protect(0xeff00..0xf0000, WriteProtect::Ignore);
+        simulate(
+            &mut registers,
+            &mut memory,
+            &mut IoBus::default(),
+            instruction,
+        );
+        assert_eq!(memory.take_faults().len(), 1);
+        memory.write8(0xeffff, 0x56);
+        assert!(memory.take_faults().is_empty());
+        assert_eq!(memory[0xeffff], 0x34);
+    }
+
+    #[test]
+    fn unpopulated_addresses() {
+        let mut memory = Memory::from(vec![0; 0x10000]);
+        memory.write16(0xffff, 0x1234);
+        assert_eq!(memory[0xffff], 0x34);
+        assert_eq!(memory.read16(0xffff), 0xff34);
+        assert_eq!(memory.read8(0xfffff), 0xff);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::snapshot::{SnapshotError, SnapshotReader, SnapshotWriter};
+
+/// Intel 8259A programmable interrupt controller.
+///
+/// Models a single (master) PIC at ports 20h/21h with fixed priority
+/// (IR0 highest), edge or level triggered inputs, IMR masking and normal or
+/// automatic EOI. The reset state matches what a PC BIOS leaves behind:
+/// vectors based at 08h, edge triggered, nothing masked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pic {
+    /// Interrupt request register.
+    pub irr: u8,
+    /// In-service register.
+    pub isr: u8,
+    /// Interrupt mask register.
+    pub imr: u8,
+    /// Vector delivered for IR0, IRn delivers `vector_base + n`.
+    pub vector_base: u8,
+    /// Current level of every input line.
+    lines: u8,
+    level_triggered: bool,
+    auto_eoi: bool,
+    read_isr: bool,
+    init: InitState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InitState {
+    Ready,
+    ExpectIcw2 { icw3: bool, icw4: bool },
+    ExpectIcw3 { icw4: bool },
+    ExpectIcw4,
+}
+
+impl Default for Pic {
+    fn default() -> Self {
+        Self {
+            irr: 0,
+            isr: 0,
+            imr: 0,
+            vector_base: 0x08,
+            lines: 0,
+            level_triggered: false,
+            auto_eoi: false,
+            read_isr: false,
+            init: InitState::Ready,
+        }
+    }
+}
+
+impl Pic {
+    pub const COMMAND_PORT: u16 = 0x20;
+    pub const DATA_PORT: u16 = 0x21;
+
+    /// Drives IRQ `line` high.
+    pub fn raise_irq(&mut self, line: u8) {
+        let bit = 1 << (line & 7);
+        if self.level_triggered || self.lines & bit == 0 {
+            self.irr |= bit;
+        }
+        self.lines |= bit;
+    }
+
+    /// Drives IRQ `line` low.
+    pub fn lower_irq(&mut self, line: u8) {
+        let bit = 1 << (line & 7);
+        self.lines &= !bit;
+        if self.level_triggered {
+            self.irr &= !bit;
+        }
+    }
+
+    /// Returns the line that would be delivered on the next acknowledge, if
+    /// any unmasked request outranks everything currently in service.
+    pub fn pending_irq(&self) -> Option<u8> {
+        let requested = self.irr & !self.imr;
+        for line in 0..8 {
+            let bit = 1 << line;
+            if self.isr & bit != 0 {
+                return None;
+            }
+            if requested & bit != 0 {
+                return Some(line);
+            }
+        }
+        None
+    }
+
+    /// Interrupt acknowledge cycle: moves the highest priority request into
+    /// service and returns its vector number.
+    pub fn acknowledge(&mut self) -> Option<u8> {
+        let line = self.pending_irq()?;
+        let bit = 1 << line;
+        self.irr &= !bit;
+        if !self.auto_eoi {
+            self.isr |= bit;
+        }
+        Some(self.vector_base.wrapping_add(line))
+    }
+
+    pub fn read(&mut self, port: u16) -> u8 {
+        match port {
+            Self::COMMAND_PORT if self.read_isr => self.isr,
+            Self::COMMAND_PORT => self.irr,
+            Self::DATA_PORT => self.imr,
+            _ => 0xff,
+        }
+    }
+
+    pub fn write(&mut self, port: u16, value: u8) {
+        match port {
+            // ICW1
+            Self::COMMAND_PORT if value & 0x10 != 0 => {
+                self.irr = 0;
+                self.isr = 0;
+                self.imr = 0;
+                self.lines = 0;
+                self.read_isr = false;
+                self.auto_eoi = false;
+                self.level_triggered = value & 0x08 != 0;
+                self.init = InitState::ExpectIcw2 {
+                    icw3: value & 0x02 == 0,
+                    icw4: value & 0x01 != 0,
+                };
+            }
+            // OCW3, read register command
+            Self::COMMAND_PORT if value & 0x0a == 0x0a => self.read_isr = value & 0x01 != 0,
+            // OCW3, other commands
+            Self::COMMAND_PORT if value & 0x08 != 0 => {}
+            // OCW2
+            Self::COMMAND_PORT => match value >> 5 {
+                // Non-specific EOI
+                0b001 => self.isr &= self.isr.wrapping_sub(1),
+                // Specific EOI
+                0b011 => self.isr &= !(1 << (value & 7)),
+                _ => {}
+            },
+            Self::DATA_PORT => match self.init {
+                InitState::ExpectIcw2 { icw3, icw4 } => {
+                    self.vector_base = value & 0xf8;
+                    self.init = match (icw3, icw4) {
+                        (true, _) => InitState::ExpectIcw3 { icw4 },
+                        (false, true) => InitState::ExpectIcw4,
+                        (false, false) => InitState::Ready,
+                    };
+                }
+                InitState::ExpectIcw3 { icw4 } => {
+                    self.init = if icw4 {
+                        InitState::ExpectIcw4
+                    } else {
+                        InitState::Ready
+                    };
+                }
+                InitState::ExpectIcw4 => {
+                    self.auto_eoi = value & 0x02 != 0;
+                    self.init = InitState::Ready;
+                }
+                // OCW1
+                InitState::Ready => self.imr = value,
+            },
+            _ => {}
+        }
+    }
+
+    pub(crate) fn save(&self, w: &mut SnapshotWriter) {
+        for value in [self.irr, self.isr, self.imr, self.vector_base, self.lines] {
+            w.u8(value);
+        }
+        w.bool(self.level_triggered);
+        w.bool(self.auto_eoi);
+        w.bool(self.read_isr);
+        let (state, icw3, icw4) = match self.init {
+            InitState::Ready => (0, false, false),
+            InitState::ExpectIcw2 { icw3, icw4 } => (1, icw3, icw4),
+            InitState::ExpectIcw3 { icw4 } => (2, false, icw4),
+            InitState::ExpectIcw4 => (3, false, false),
+        };
+        w.u8(state);
+        w.bool(icw3);
+        w.bool(icw4);
+    }
+
+    pub(crate) fn restore(r: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let mut pic = Self {
+            irr: r.u8()?,
+            isr: r.u8()?,
+            imr: r.u8()?,
+            vector_base: r.u8()?,
+            lines: r.u8()?,
+            level_triggered: r.bool()?,
+            auto_eoi: r.bool()?,
+            read_isr: r.bool()?,
+            init: InitState::Ready,
+        };
+        let (state, icw3, icw4) = (r.u8()?, r.bool()?, r.bool()?);
+        pic.init = match state {
+            0 => InitState::Ready,
+            1 => InitState::ExpectIcw2 { icw3, icw4 },
+            2 => InitState::ExpectIcw3 { icw4 },
+            3 => InitState::ExpectIcw4,
+            _ => return Err(SnapshotError::Corrupt),
+        };
+        Ok(pic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priority_and_eoi() {
+        let mut pic = Pic::default();
+        pic.raise_irq(3);
+        pic.raise_irq(1);
+
+        assert_eq!(pic.acknowledge(), Some(0x09));
+        // IR3 is blocked while the higher priority IR1 is in service.
+        assert_eq!(pic.acknowledge(), None);
+
+        pic.write(Pic::COMMAND_PORT, 0x20);
+        assert_eq!(pic.acknowledge(), Some(0x0b));
+        assert_eq!(pic.isr, 0b1000);
+    }
+
+    #[test]
+    fn higher_priority_preempts() {
+        let mut pic = Pic::default();
+        pic.raise_irq(4);
+        assert_eq!(pic.acknowledge(), Some(0x0c));
+
+        pic.raise_irq(0);
+        assert_eq!(pic.acknowledge(), Some(0x08));
+        assert_eq!(pic.isr, 0b10001);
+
+        pic.write(Pic::COMMAND_PORT, 0x20);
+        assert_eq!(pic.isr, 0b10000);
+    }
+
+    #[test]
+    fn masking() {
+        let mut pic = Pic::default();
+        pic.write(Pic::DATA_PORT, 0b0000_0001);
+        pic.raise_irq(0);
+        assert_eq!(pic.pending_irq(), None);
+        assert_eq!(pic.read(Pic::DATA_PORT), 0b0000_0001);
+
+        pic.write(Pic::DATA_PORT, 0);
+        assert_eq!(pic.pending_irq(), Some(0));
+    }
+
+    #[test]
+    fn edge_triggered_needs_new_edge() {
+        let mut pic = Pic::default();
+        pic.raise_irq(2);
+        assert_eq!(pic.acknowledge(), Some(0x0a));
+        pic.write(Pic::COMMAND_PORT, 0x20);
+
+        pic.raise_irq(2);
+        assert_eq!(pic.pending_irq(), None);
+
+        pic.lower_irq(2);
+        pic.raise_irq(2);
+        assert_eq!(pic.pending_irq(), Some(2));
+    }
+
+    #[test]
+    fn initialization_sequence() {
+        let mut pic = Pic::default();
+        // ICW1: edge, single, ICW4 needed. ICW2: base 70h. ICW4: 8086, auto EOI.
+        pic.write(Pic::COMMAND_PORT, 0x13);
+        pic.write(Pic::DATA_PORT, 0x70);
+        pic.write(Pic::DATA_PORT, 0x03);
+        pic.write(Pic::DATA_PORT, 0xff);
+        assert_eq!(pic.imr, 0xff);
+
+        pic.raise_irq(0);
+        assert_eq!(pic.pending_irq(), None);
+        pic.write(Pic::DATA_PORT, 0x00);
+        assert_eq!(pic.acknowledge(), Some(0x70));
+        assert_eq!(pic.isr, 0);
+
+        // OCW3: read ISR / IRR.
+        pic.raise_irq(5);
+        pic.write(Pic::COMMAND_PORT, 0x0a);
+        assert_eq!(pic.read(Pic::COMMAND_PORT), 0b10_0000);
+        pic.write(Pic::COMMAND_PORT, 0x0b);
+        assert_eq!(pic.read(Pic::COMMAND_PORT), 0);
+    }
+}
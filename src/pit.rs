@@ -0,0 +1,398 @@
+use crate::snapshot::{SnapshotError, SnapshotReader, SnapshotWriter};
+
+/// Intel 8253/8254 programmable interval timer.
+///
+/// Three counters at ports 40h–42h with the control register at 43h.
+/// Modes 0 (interrupt on terminal count), 2 (rate generator) and
+/// 3 (square wave) are emulated; counters programmed with any other mode
+/// stay idle with their output high. Counting is binary only, the BCD bit is
+/// stored and reported but otherwise ignored. All gates are tied high.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Pit {
+    pub channels: [PitChannel; 3],
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PitChannel {
+    pub mode: u8,
+    pub access: PitAccess,
+    pub bcd: bool,
+    /// Count register, the value loaded on (re)start. Zero stands for 65536.
+    pub reload: u16,
+    /// Counting element.
+    pub count: u16,
+    pub output: bool,
+    counting: bool,
+    load_pending: bool,
+    null_count: bool,
+    reloaded: bool,
+    write_msb_next: bool,
+    read_msb_next: bool,
+    write_lsb: u8,
+    latch: Option<u16>,
+    status_latch: Option<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PitAccess {
+    #[default]
+    LsbMsb,
+    Lsb,
+    Msb,
+}
+
+impl Pit {
+    pub const COUNTER0_PORT: u16 = 0x40;
+    pub const COUNTER1_PORT: u16 = 0x41;
+    pub const COUNTER2_PORT: u16 = 0x42;
+    pub const CONTROL_PORT: u16 = 0x43;
+
+    /// PIT input clock: the 14.31818 MHz crystal divided by 12, which is
+    /// exactly a quarter of the 4.77 MHz CPU clock.
+    pub const CPU_CYCLES_PER_CLOCK: u32 = 4;
+
+    /// Applies one input clock pulse to every counter.
+    pub fn clock(&mut self) {
+        for channel in &mut self.channels {
+            channel.clock();
+        }
+    }
+
+    pub fn read(&mut self, port: u16) -> u8 {
+        match port {
+            Self::COUNTER0_PORT..=Self::COUNTER2_PORT => {
+                self.channels[(port - Self::COUNTER0_PORT) as usize].read()
+            }
+            // The control register is write only.
+            _ => 0xff,
+        }
+    }
+
+    pub fn write(&mut self, port: u16, value: u8) {
+        match port {
+            Self::COUNTER0_PORT..=Self::COUNTER2_PORT => {
+                self.channels[(port - Self::COUNTER0_PORT) as usize].write(value)
+            }
+            Self::CONTROL_PORT => self.write_control(value),
+            _ => {}
+        }
+    }
+
+    fn write_control(&mut self, value: u8) {
+        let select = value >> 6;
+        if select == 0b11 {
+            // Read-back command (8254 only).
+            for (i, channel) in self.channels.iter_mut().enumerate() {
+                if value & (0b10 << i) == 0 {
+                    continue;
+                }
+                if value & 0x10 == 0 && channel.status_latch.is_none() {
+                    channel.status_latch = Some(channel.status());
+                }
+                if value & 0x20 == 0 && channel.latch.is_none() {
+                    channel.latch = Some(channel.count);
+                }
+            }
+            return;
+        }
+
+        let channel = &mut self.channels[select as usize];
+        let access = match (value >> 4) & 0b11 {
+            0b00 => {
+                // Counter latch command.
+                if channel.latch.is_none() {
+                    channel.latch = Some(channel.count);
+                }
+                return;
+            }
+            0b01 => PitAccess::Lsb,
+            0b10 => PitAccess::Msb,
+            _ => PitAccess::LsbMsb,
+        };
+
+        *channel = PitChannel {
+            mode: match (value >> 1) & 0b111 {
+                0b110 => 2,
+                0b111 => 3,
+                mode => mode,
+            },
+            access,
+            bcd: value & 1 != 0,
+            reload: channel.reload,
+            count: channel.count,
+            output: (value >> 1) & 0b111 != 0,
+            null_count: true,
+            ..Default::default()
+        };
+    }
+}
+
+impl PitChannel {
+    fn clock(&mut self) {
+        if !self.counting {
+            return;
+        }
+
+        if self.load_pending {
+            self.load_pending = false;
+            self.null_count = false;
+            self.reloaded = true;
+            self.count = self.reload;
+            return;
+        }
+
+        match self.mode {
+            0 => {
+                self.count = self.count.wrapping_sub(1);
+                if self.count == 0 {
+                    self.output = true;
+                }
+            }
+            2 => {
+                self.count = self.count.wrapping_sub(1);
+                if self.count == 1 {
+                    self.output = false;
+                } else if self.count == 0 {
+                    self.output = true;
+                    self.count = self.reload;
+                    self.null_count = false;
+                }
+            }
+            3 => {
+                // Odd counts spend one extra clock in the high half.
+                let step = match (self.reloaded && self.reload & 1 == 1, self.output) {
+                    (true, true) => 1,
+                    (true, false) => 3,
+                    (false, _) => 2,
+                };
+                self.reloaded = false;
+
+                if self.count != 0 && self.count <= step {
+                    self.output = !self.output;
+                    self.count = self.reload;
+                    self.null_count = false;
+                    self.reloaded = true;
+                } else {
+                    self.count = self.count.wrapping_sub(step);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        let reload = match self.access {
+            PitAccess::Lsb => value as u16,
+            PitAccess::Msb => (value as u16) << 8,
+            PitAccess::LsbMsb if !self.write_msb_next => {
+                self.write_lsb = value;
+                self.write_msb_next = true;
+                return;
+            }
+            PitAccess::LsbMsb => {
+                self.write_msb_next = false;
+                u16::from_le_bytes([self.write_lsb, value])
+            }
+        };
+
+        self.reload = reload;
+        self.null_count = true;
+        match self.mode {
+            0 => {
+                self.output = false;
+                self.counting = true;
+                self.load_pending = true;
+            }
+            // A new count only takes effect at the end of the current period.
+            2 | 3 if !self.counting => {
+                self.counting = true;
+                self.load_pending = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        if let Some(status) = self.status_latch.take() {
+            return status;
+        }
+
+        let value = self.latch.unwrap_or(self.count);
+        let [lo, hi] = value.to_le_bytes();
+        match self.access {
+            PitAccess::Lsb => {
+                self.latch = None;
+                lo
+            }
+            PitAccess::Msb => {
+                self.latch = None;
+                hi
+            }
+            PitAccess::LsbMsb if !self.read_msb_next => {
+                self.read_msb_next = true;
+                lo
+            }
+            PitAccess::LsbMsb => {
+                self.read_msb_next = false;
+                self.latch = None;
+                hi
+            }
+        }
+    }
+
+    fn status(&self) -> u8 {
+        let access = match self.access {
+            PitAccess::Lsb => 0b01,
+            PitAccess::Msb => 0b10,
+            PitAccess::LsbMsb => 0b11,
+        };
+        (self.output as u8) << 7
+            | (self.null_count as u8) << 6
+            | access << 4
+            | self.mode << 1
+            | self.bcd as u8
+    }
+
+    pub(crate) fn save(&self, w: &mut SnapshotWriter) {
+        w.u8(self.mode);
+        w.u8(self.access as u8);
+        w.bool(self.bcd);
+        w.u16(self.reload);
+        w.u16(self.count);
+        for value in [
+            self.output,
+            self.counting,
+            self.load_pending,
+            self.null_count,
+            self.reloaded,
+            self.write_msb_next,
+            self.read_msb_next,
+        ] {
+            w.bool(value);
+        }
+        w.u8(self.write_lsb);
+        w.bool(self.latch.is_some());
+        w.u16(self.latch.unwrap_or(0));
+        w.bool(self.status_latch.is_some());
+        w.u8(self.status_latch.unwrap_or(0));
+    }
+
+    pub(crate) fn restore(r: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let mode = r.u8()?;
+        let access = match r.u8()? {
+            0 => PitAccess::LsbMsb,
+            1 => PitAccess::Lsb,
+            2 => PitAccess::Msb,
+            _ => return Err(SnapshotError::Corrupt),
+        };
+        if mode > 5 {
+            return Err(SnapshotError::Corrupt);
+        }
+        Ok(Self {
+            mode,
+            access,
+            bcd: r.bool()?,
+            reload: r.u16()?,
+            count: r.u16()?,
+            output: r.bool()?,
+            counting: r.bool()?,
+            load_pending: r.bool()?,
+            null_count: r.bool()?,
+            reloaded: r.bool()?,
+            write_msb_next: r.bool()?,
+            read_msb_next: r.bool()?,
+            write_lsb: r.u8()?,
+            latch: {
+                let present = r.bool()?;
+                let value = r.u16()?;
+                present.then_some(value)
+            },
+            status_latch: {
+                let present = r.bool()?;
+                let value = r.u8()?;
+                present.then_some(value)
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(pit: &mut Pit, control: u8, reload: u16) {
+        let [lo, hi] = reload.to_le_bytes();
+        pit.write(Pit::CONTROL_PORT, control);
+        pit.write(Pit::COUNTER0_PORT, lo);
+        pit.write(Pit::COUNTER0_PORT, hi);
+    }
+
+    fn outputs(pit: &mut Pit, clocks: usize) -> Vec<bool> {
+        (0..clocks)
+            .map(|_| {
+                pit.clock();
+                pit.channels[0].output
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mode0_interrupt_on_terminal_count() {
+        let mut pit = Pit::default();
+        program(&mut pit, 0b0011_0000, 3);
+        assert!(!pit.channels[0].output);
+
+        // One clock loads the count, three more reach terminal count.
+        let out = outputs(&mut pit, 6);
+        assert_eq!(out, [false, false, false, true, true, true]);
+    }
+
+    #[test]
+    fn mode2_rate_generator() {
+        let mut pit = Pit::default();
+        program(&mut pit, 0b0011_0100, 4);
+
+        let out = outputs(&mut pit, 9);
+        assert_eq!(
+            out,
+            [true, true, true, false, true, true, true, false, true]
+        );
+    }
+
+    #[test]
+    fn mode3_square_wave() {
+        let mut pit = Pit::default();
+        program(&mut pit, 0b0011_0110, 4);
+        let out = outputs(&mut pit, 9);
+        assert_eq!(
+            out,
+            [true, true, false, false, true, true, false, false, true]
+        );
+
+        // Odd counts are high for (n + 1) / 2 and low for (n - 1) / 2 clocks.
+        program(&mut pit, 0b0011_0110, 5);
+        let out = outputs(&mut pit, 11);
+        assert_eq!(
+            out,
+            [true, true, true, false, false, true, true, true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn latch_and_read_back() {
+        let mut pit = Pit::default();
+        program(&mut pit, 0b0011_0100, 0x1234);
+        pit.clock();
+        pit.clock();
+
+        pit.write(Pit::CONTROL_PORT, 0b0000_0000);
+        pit.clock();
+        assert_eq!(pit.read(Pit::COUNTER0_PORT), 0x33);
+        assert_eq!(pit.read(Pit::COUNTER0_PORT), 0x12);
+        assert_eq!(pit.read(Pit::COUNTER0_PORT), 0x32);
+
+        // Read-back status of counter 0: output high, LSB/MSB, mode 2.
+        pit.write(Pit::CONTROL_PORT, 0b1110_0010);
+        assert_eq!(pit.read(Pit::COUNTER0_PORT), 0b1011_0100);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::*;
+
+/// A byte written through the bus by a recorded step, with the RAM contents
+/// either side of it. They are equal when the write stored the value already
+/// there, or went to a ROM or device mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: usize,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// One recorded step: the registers on either side of it and every byte it
+/// wrote, in the order written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub before: RegisterFile,
+    pub after: RegisterFile,
+    pub writes: Vec<MemoryWrite>,
+}
+
+/// Execution history that can be walked backward and forward.
+///
+/// Writes are taken from the bus as they happen, so changes made directly
+/// to the RAM array between steps are neither recorded nor undone. Device
+/// state, whether on the I/O bus or memory mapped, is not rewound.
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    steps: Vec<Step>,
+    /// Number of steps currently applied to the machine.
+    position: usize,
+}
+
+impl Recording {
+    /// Index of the instruction that executes next.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Runs `step` as the next recorded step. A recording that was rewound
+    /// drops everything after the current position first.
+    pub fn record<R>(
+        &mut self,
+        registers: &mut RegisterFile,
+        memory: &mut Memory,
+        step: impl FnOnce(&mut RegisterFile, &mut Memory) -> R,
+    ) -> R {
+        self.steps.truncate(self.position);
+
+        let before = *registers;
+        memory.start_journal();
+        let result = step(registers, memory);
+
+        self.steps.push(Step {
+            before,
+            after: *registers,
+            writes: memory.take_journal(),
+        });
+        self.position += 1;
+        result
+    }
+
+    /// Undoes the last applied step. Returns false at the start of the
+    /// recording.
+    pub fn step_back(&mut self, registers: &mut RegisterFile, memory: &mut Memory) -> bool {
+        if self.position == 0 {
+            return false;
+        }
+        self.position -= 1;
+
+        let step = &self.steps[self.position];
+        for write in step.writes.iter().rev() {
+            if let Some(byte) = memory.get_mut(write.address) {
+                *byte = write.old;
+            }
+        }
+        *registers = step.before;
+        true
+    }
+
+    /// Reapplies the next recorded step. Returns false at the end of the
+    /// recording.
+    pub fn step_forward(&mut self, registers: &mut RegisterFile, memory: &mut Memory) -> bool {
+        let Some(step) = self.steps.get(self.position) else {
+            return false;
+        };
+        self.position += 1;
+
+        for write in &step.writes {
+            if let Some(byte) = memory.get_mut(write.address) {
+                *byte = write.new;
+            }
+        }
+        *registers = step.after;
+        true
+    }
+
+    /// Moves to the state right before instruction `index`, clamped to the
+    /// end of the recording.
+    pub fn seek(&mut self, index: usize, registers: &mut RegisterFile, memory: &mut Memory) {
+        let index = index.min(self.steps.len());
+        while self.position > index {
+            self.step_back(registers, memory);
+        }
+        while self.position < index {
+            self.step_forward(registers, memory);
+        }
+    }
+
+    /// Steps back to right before the most recent step that wrote the byte
+    /// at `address` and returns its index. Nothing moves if no applied step
+    /// wrote it.
+    pub fn run_back_to_write(
+        &mut self,
+        address: usize,
+        registers: &mut RegisterFile,
+        memory: &mut Memory,
+    ) -> Option<usize> {
+        let index = self.steps[..self.position]
+            .iter()
+            .rposition(|step| step.writes.iter().any(|write| write.address == address))?;
+        self.seek(index, registers, memory);
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::physical_address;
+
+    fn run(
+        recording: &mut Recording,
+        registers: &mut RegisterFile,
+        memory: &mut Memory,
+        io: &mut IoBus,
+        steps: usize,
+    ) {
+        for _ in 0..steps {
+            recording.record(registers, memory, |registers, memory| {
+                let start = physical_address(registers.cs, registers.ip);
+                let instruction = decode_instruction(&memory[start..start + 16]);
+                simulate(registers, memory, io, instruction)
+            });
+        }
+    }
+
+    /// mov word [0x100], 0x1234 / inc byte [0x100] / xchg [0x1fe], ax /
+    /// inc byte [0x100] / nop
+    fn program() -> Memory {
+        let mut memory = Memory::default();
+        memory[..19].copy_from_slice(&[
+            0xc7, 0x06, 0x00, 0x01, 0x34, 0x12, 0xfe, 0x06, 0x00, 0x01, 0x87, 0x06, 0xfe, 0x01,
+            0xfe, 0x06, 0x00, 0x01, 0x90,
+        ]);
+        memory
+    }
+
+    #[test]
+    fn steps_back_and_forward() {
+        let mut memory = program();
+        let mut registers = RegisterFile {
+            ax: 0xbeef,
+            ..Default::default()
+        };
+        let mut io = IoBus::default();
+        let mut recording = Recording::default();
+        run(&mut recording, &mut registers, &mut memory, &mut io, 4);
+
+        assert_eq!(recording.len(), 4);
+        assert_eq!(&memory[0x100..0x102], &[0x36, 0x12]);
+        assert_eq!(&memory[0x1fe..0x200], &[0xef, 0xbe]);
+        assert_eq!(recording.steps()[2].writes.len(), 2);
+
+        assert!(recording.step_back(&mut registers, &mut memory));
+        assert!(recording.step_back(&mut registers, &mut memory));
+        assert_eq!(recording.position(), 2);
+        assert_eq!((registers.ip, registers.ax), (10, 0xbeef));
+        assert_eq!(&memory[0x100..0x102], &[0x35, 0x12]);
+        assert_eq!(&memory[0x1fe..0x200], &[0, 0]);
+
+        recording.seek(0, &mut registers, &mut memory);
+        assert!(!recording.step_back(&mut registers, &mut memory));
+        assert_eq!(registers.ip, 0);
+        assert_eq!(memory[..], program()[..]);
+
+        recording.seek(usize::MAX, &mut registers, &mut memory);
+        assert_eq!(recording.position(), 4);
+        assert_eq!((registers.ip, registers.ax), (18, 0));
+        assert_eq!(&memory[0x100..0x102], &[0x36, 0x12]);
+    }
+
+    #[test]
+    fn runs_back_to_last_write() {
+        let mut memory = program();
+        let mut registers = RegisterFile::default();
+        let mut io = IoBus::default();
+        let mut recording = Recording::default();
+        run(&mut recording, &mut registers, &mut memory, &mut io, 5);
+
+        // The second inc is the last to touch the low byte.
+        assert_eq!(
+            recording.run_back_to_write(0x100, &mut registers, &mut memory),
+            Some(3)
+        );
+        assert_eq!(registers.ip, 14);
+        assert_eq!(memory[0x100], 0x35);
+
+        // Only the mov wrote the high byte.
+        assert_eq!(
+            recording.run_back_to_write(0x101, &mut registers, &mut memory),
+            Some(0)
+        );
+        assert_eq!(
+            recording.run_back_to_write(0x300, &mut registers, &mut memory),
+            None
+        );
+        assert_eq!(recording.position(), 0);
+
+        // Executing from a rewound position replaces the old future.
+        recording.step_forward(&mut registers, &mut memory);
+        run(&mut recording, &mut registers, &mut memory, &mut io, 1);
+        assert_eq!(recording.len(), 2);
+        assert_eq!(memory[0x100], 0x35);
+    }
+
+    #[test]
+    fn records_writes_that_change_nothing() {
+        // mov byte [0x200], 0x55 / nop / mov byte [0x200], 0x55 / nop
+        let mut memory = Memory::default();
+        memory[..12].copy_from_slice(&[
+            0xc6, 0x06, 0x00, 0x02, 0x55, 0x90, 0xc6, 0x06, 0x00, 0x02, 0x55, 0x90,
+        ]);
+        let mut registers = RegisterFile::default();
+        let mut io = IoBus::default();
+        let mut recording = Recording::default();
+        run(&mut recording, &mut registers, &mut memory, &mut io, 4);
+
+        assert_eq!(
+            recording.steps()[2].writes,
+            [MemoryWrite {
+                address: 0x200,
+                old: 0x55,
+                new: 0x55
+            }]
+        );
+        assert_eq!(
+            recording.run_back_to_write(0x200, &mut registers, &mut memory),
+            Some(2)
+        );
+        assert_eq!(registers.ip, 6);
+        assert_eq!(memory[0x200], 0x55);
+
+        // Writes a ROM swallows are recorded too.
+        memory.protect(0x300..0x301, WriteProtect::Ignore);
+        registers.ip = 0;
+        memory[2..4].copy_from_slice(&[0x00, 0x03]);
+        run(&mut recording, &mut registers, &mut memory, &mut io, 1);
+        assert_eq!(recording.steps()[2].writes[0].address, 0x300);
+        assert_eq!(memory[0x300], 0);
+    }
+}
@@ -0,0 +1,146 @@
+use std::io::{self, Write};
+
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterWidth {
+    Byte,
+    Word,
+}
+
+/// The bits of a 16-bit register an operand names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part {
+    Low,
+    High,
+    Whole,
+}
+
+impl Register {
+    pub fn width(self) -> RegisterWidth {
+        match self.part() {
+            Part::Low | Part::High => RegisterWidth::Byte,
+            Part::Whole => RegisterWidth::Word,
+        }
+    }
+
+    fn part(self) -> Part {
+        match self {
+            Register::AL | Register::CL | Register::DL | Register::BL => Part::Low,
+            Register::AH | Register::CH | Register::DH | Register::BH => Part::High,
+            _ => Part::Whole,
+        }
+    }
+}
+
+impl RegisterFile {
+    /// Reads a general purpose register. Byte registers are zero extended;
+    /// AL is the low byte of AX and AH the high byte, whatever the host's
+    /// byte order.
+    pub fn read(&self, reg: Register) -> u16 {
+        let value = match reg {
+            Register::AL | Register::AH | Register::AX => self.ax,
+            Register::CL | Register::CH | Register::CX => self.cx,
+            Register::DL | Register::DH | Register::DX => self.dx,
+            Register::BL | Register::BH | Register::BX => self.bx,
+            Register::SP => self.sp,
+            Register::BP => self.bp,
+            Register::SI => self.si,
+            Register::DI => self.di,
+        };
+        match reg.part() {
+            Part::Low => value & 0xff,
+            Part::High => value >> 8,
+            Part::Whole => value,
+        }
+    }
+
+    /// Writes a general purpose register. Byte registers take the low byte
+    /// of `value` and leave the other half of their word alone.
+    pub fn write(&mut self, reg: Register, value: u16) {
+        let word = match reg {
+            Register::AL | Register::AH | Register::AX => &mut self.ax,
+            Register::CL | Register::CH | Register::CX => &mut self.cx,
+            Register::DL | Register::DH | Register::DX => &mut self.dx,
+            Register::BL | Register::BH | Register::BX => &mut self.bx,
+            Register::SP => &mut self.sp,
+            Register::BP => &mut self.bp,
+            Register::SI => &mut self.si,
+            Register::DI => &mut self.di,
+        };
+        *word = match reg.part() {
+            Part::Low => *word & 0xff00 | value & 0x00ff,
+            Part::High => *word & 0x00ff | (value & 0x00ff) << 8,
+            Part::Whole => value,
+        };
+    }
+
+    /// Writes the `register.txt` listing: the general purpose registers in
+    /// hex, the flags in binary and IP in decimal.
+    pub fn write_report(&self, output: &mut impl Write) -> io::Result<()> {
+        writeln!(output, "ax: {:#06x}", self.ax)?;
+        writeln!(output, "bx: {:#06x}", self.bx)?;
+        writeln!(output, "cx: {:#06x}", self.cx)?;
+        writeln!(output, "dx: {:#06x}", self.dx)?;
+        writeln!(output, "sp: {:#06x}", self.sp)?;
+        writeln!(output, "bp: {:#06x}", self.bp)?;
+        writeln!(output, "si: {:#06x}", self.si)?;
+        writeln!(output, "di: {:#06x}", self.di)?;
+        writeln!(output, "flags: {:016b}", self.flags)?;
+        writeln!(output, "ip: {}", self.ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_halves() {
+        let mut registers = RegisterFile {
+            ax: 0x1234,
+            ..Default::default()
+        };
+        assert_eq!(registers.read(Register::AL), 0x34);
+        assert_eq!(registers.read(Register::AH), 0x12);
+
+        registers.write(Register::AH, 0xffab);
+        assert_eq!(registers.ax, 0xab34);
+        registers.write(Register::AL, 0x00cd);
+        assert_eq!(registers.ax, 0xabcd);
+        assert_eq!(registers.read(Register::AX), 0xabcd);
+    }
+
+    #[test]
+    fn every_register() {
+        let words = [
+            (Register::AX, Register::AL, Register::AH),
+            (Register::CX, Register::CL, Register::CH),
+            (Register::DX, Register::DL, Register::DH),
+            (Register::BX, Register::BL, Register::BH),
+        ];
+        for (i, (word, low, high)) in words.into_iter().enumerate() {
+            let mut registers = RegisterFile::default();
+            registers.write(word, 0x0102 * (i as u16 + 1));
+            assert_eq!(registers.read(low), 2 * (i as u16 + 1));
+            assert_eq!(registers.read(high), i as u16 + 1);
+            assert_eq!(word.width(), RegisterWidth::Word);
+            assert_eq!(low.width(), RegisterWidth::Byte);
+            assert_eq!(high.width(), RegisterWidth::Byte);
+        }
+
+        let mut registers = RegisterFile::default();
+        for (i, reg) in [Register::SP, Register::BP, Register::SI, Register::DI]
+            .into_iter()
+            .enumerate()
+        {
+            registers.write(reg, 0x1000 + i as u16);
+            assert_eq!(registers.read(reg), 0x1000 + i as u16);
+        }
+        assert_eq!(
+            (registers.sp, registers.bp, registers.si, registers.di),
+            (0x1000, 0x1001, 0x1002, 0x1003)
+        );
+        assert_eq!(registers.ax | registers.bx | registers.cx | registers.dx, 0);
+    }
+}
@@ -1,385 +1,2082 @@
 use crate::*;
 
-pub fn simulate(registers: &mut RegisterFile, memory: &mut [u8], instruction: Instruction) {
-    registers.ip += instruction.length as u16;
+pub fn simulate(
+    registers: &mut RegisterFile,
+    memory: &mut dyn Bus,
+    io: &mut IoBus,
+    instruction: Instruction,
+) -> u32 {
+    memory.set_instruction(registers.cs, registers.ip);
+    registers.ip = registers.ip.wrapping_add(instruction.length as u16);
+    registers.interrupt_shadow = false;
+    let mut cycles = base_cycles(&instruction);
 
     match instruction.op {
         Op::Mov => {
             let dest = instruction.operands[0].expect("mov must have operands");
             let src = instruction.operands[1].expect("mov must have operands");
+            let value = read_operand(registers, memory, src);
+            write_operand(registers, memory, dest, value);
+        }
+        Op::Add | Op::Adc | Op::Sub | Op::Sbb | Op::Cmp | Op::And | Op::Or | Op::Xor | Op::Test => {
+            let dest = instruction.operands[0].expect("alu ops must have operands");
+            let src = instruction.operands[1].expect("alu ops must have operands");
 
-            match src {
-                Operand::Register(reg) => {
-                    let (src_value, size, offset) = get_register_value(registers, reg);
-                    let src_value = src_value.to_ne_bytes();
-                    let end = (size + offset) as usize;
-                    let src_slice = &src_value[offset as usize..end];
-
-                    match dest {
-                        Operand::Register(reg) => {
-                            let dest = get_register_as_slice(registers, reg);
-                            dest.copy_from_slice(src_slice);
-                        }
-                        Operand::Memory(memory_operand) => {
-                            let addr = get_address_from_operand(registers, memory_operand);
-                            let dest_memory = &mut memory[addr..addr + size as usize];
-                            dest_memory.copy_from_slice(src_slice);
-                        }
-                        Operand::Immediate(_) => unreachable!(),
-                    };
-                }
-                Operand::Immediate(imm) => {
-                    let (imm, size) = match imm {
-                        Immediate::Bit8(value) => (value as u16, 1),
-                        Immediate::Bit16(value) => (value, 2),
-                    };
-                    let src_slice = u16_as_byte_slice(&imm, size);
-                    match dest {
-                        Operand::Register(reg) => {
-                            let dest = get_register_as_slice(registers, reg);
-                            dest.copy_from_slice(src_slice);
-                        }
-                        Operand::Memory(memory_operand) => {
-                            let addr = get_address_from_operand(registers, memory_operand);
-                            let dest_memory = &mut memory[addr..addr + size as usize];
-                            dest_memory.copy_from_slice(src_slice);
-                        }
-                        Operand::Immediate(_) => unreachable!(),
-                    };
-                }
-                Operand::Memory(memory_operand) => {
-                    let addr = get_address_from_operand(registers, memory_operand);
-
-                    match dest {
-                        Operand::Register(reg) => {
-                            let dest = get_register_as_slice(registers, reg);
-                            let src_memory = &mut memory[addr..addr + dest.len()];
-                            dest.copy_from_slice(src_memory);
-                        }
-                        Operand::Memory(_) => unreachable!(),
-                        Operand::Immediate(_) => unreachable!(),
-                    };
-                }
+            let word = operand_is_word(dest);
+            let dest_value = read_operand(registers, memory, dest);
+            let src_value = match src {
+                // 83h sign-extends its byte immediate.
+                Operand::Immediate(Immediate::Bit8(value)) if word => value as i8 as u16,
+                _ => read_operand(registers, memory, src),
             };
-        }
-        Op::Add => {
-            let dest = instruction.operands[0].expect("add must have operands");
-            let src = instruction.operands[1].expect("add must have operands");
+            let carry = registers.flags & RegisterFile::CF_MASK;
 
-            match src {
-                Operand::Register(reg) => {
-                    let (src_value, _, offset) = get_register_value(registers, reg);
-
-                    match dest {
-                        Operand::Register(reg) => {
-                            let (dest_value, size, _) = get_register_value(registers, reg);
-                            let sum = src_value + dest_value;
-
-                            let sum_bytes = sum.to_ne_bytes();
-                            let end = (size + offset) as usize;
-                            let sum_slice = &sum_bytes[offset as usize..end];
-                            let dest = get_register_as_slice(registers, reg);
-                            dest.copy_from_slice(sum_slice);
-                            if size == 1 {
-                                set_flags(&mut registers.flags, sum, sum_slice[0], sum_slice[0]);
-                            } else {
-                                set_flags(&mut registers.flags, sum, sum_slice[0], sum_slice[1]);
-                            }
-                        }
-                        Operand::Memory(_) => unimplemented!(),
-                        Operand::Immediate(_) => unreachable!(),
-                    };
-                }
-                Operand::Immediate(imm) => {
-                    let (imm, size) = match imm {
-                        Immediate::Bit8(value) => (value as u16, 1),
-                        Immediate::Bit16(value) => (value, 2),
-                    };
-                    match dest {
-                        Operand::Register(reg) => {
-                            let (dest_value, size, offset) = get_register_value(registers, reg);
-                            let sum = imm + dest_value;
-
-                            let sum_bytes = sum.to_ne_bytes();
-                            let end = (size + offset) as usize;
-                            let sum_slice = &sum_bytes[offset as usize..end];
-                            let dest = get_register_as_slice(registers, reg);
-                            dest.copy_from_slice(sum_slice);
-                            if size == 1 {
-                                set_flags(&mut registers.flags, sum, sum_slice[0], sum_slice[0]);
-                            } else {
-                                set_flags(&mut registers.flags, sum, sum_slice[0], sum_slice[1]);
-                            }
-                        }
-                        Operand::Memory(_) => unimplemented!(),
-                        Operand::Immediate(_) => unreachable!(),
-                    };
+            let flags = &mut registers.flags;
+            let result = match instruction.op {
+                Op::Add => add_flags(flags, dest_value, src_value, 0, word),
+                Op::Adc => add_flags(flags, dest_value, src_value, carry, word),
+                Op::Sub | Op::Cmp => sub_flags(flags, dest_value, src_value, 0, word),
+                Op::Sbb => sub_flags(flags, dest_value, src_value, carry, word),
+                Op::And | Op::Test => logic_flags(flags, dest_value & src_value, word),
+                Op::Or => logic_flags(flags, dest_value | src_value, word),
+                Op::Xor => logic_flags(flags, dest_value ^ src_value, word),
+                _ => unreachable!(),
+            };
+
+            if !matches!(instruction.op, Op::Cmp | Op::Test) {
+                write_operand(registers, memory, dest, result);
+            }
+        }
+        Op::Not => {
+            let dest = instruction.operands[0].expect("not must have operand");
+            let value = read_operand(registers, memory, dest);
+            write_operand(registers, memory, dest, !value);
+        }
+        Op::Neg => {
+            let dest = instruction.operands[0].expect("neg must have operand");
+            let value = read_operand(registers, memory, dest);
+            let word = operand_is_word(dest);
+            let result = sub_flags(&mut registers.flags, 0, value, 0, word);
+            write_operand(registers, memory, dest, result);
+        }
+        Op::Shl | Op::Shr | Op::Sar | Op::Rol | Op::Ror | Op::Rcl | Op::Rcr => {
+            let dest = instruction.operands[0].expect("shifts must have operands");
+            let count = match instruction.operands[1] {
+                Some(Operand::Immediate(Immediate::Bit8(count))) => count,
+                Some(Operand::Register(Register::CL)) => {
+                    let count = registers.cx as u8;
+                    cycles += 4 * count as u32;
+                    count
                 }
-                Operand::Memory(_) => unimplemented!(),
+                _ => unreachable!(),
             };
+
+            let value = read_operand(registers, memory, dest);
+            let word = operand_is_word(dest);
+            let result = shift_rotate(&mut registers.flags, instruction.op, value, count, word);
+            write_operand(registers, memory, dest, result);
+        }
+        Op::Je
+        | Op::Jl
+        | Op::Jle
+        | Op::Jb
+        | Op::Jbe
+        | Op::Jp
+        | Op::Jo
+        | Op::Js
+        | Op::Jne
+        | Op::Jnl
+        | Op::Jg
+        | Op::Jnb
+        | Op::Ja
+        | Op::Jnp
+        | Op::Jno
+        | Op::Jns => {
+            let ip_inc = instruction.operands[0].expect("jumps must have operand");
+            if condition(registers.flags, instruction.op) {
+                relative_jump(registers, ip_inc);
+                cycles += 12;
+            }
         }
-        Op::Sub => {
-            let dest = instruction.operands[0].expect("sub must have operands");
-            let src = instruction.operands[1].expect("sub must have operands");
+        Op::Loop | Op::Loopz | Op::Loopnz => {
+            let ip_inc = instruction.operands[0].expect("loops must have operand");
+            registers.cx = registers.cx.wrapping_sub(1);
 
-            match src {
-                Operand::Register(reg) => {
-                    let (src_value, _, offset) = get_register_value(registers, reg);
-
-                    match dest {
-                        Operand::Register(reg) => {
-                            let (dest_value, size, _) = get_register_value(registers, reg);
-                            let diff = (dest_value as i16 - src_value as i16) as u16;
-
-                            let diff_bytes = diff.to_ne_bytes();
-                            let end = (size + offset) as usize;
-                            let diff_slice = &diff_bytes[offset as usize..end];
-                            let dest = get_register_as_slice(registers, reg);
-                            dest.copy_from_slice(diff_slice);
-
-                            if size == 1 {
-                                set_flags(&mut registers.flags, diff, diff_slice[0], diff_slice[0]);
-                            } else {
-                                set_flags(&mut registers.flags, diff, diff_slice[0], diff_slice[1]);
-                            }
-                        }
-                        Operand::Memory(_) => unimplemented!(),
-                        Operand::Immediate(_) => unreachable!(),
-                    };
-                }
-                Operand::Immediate(imm) => {
-                    let (imm, _) = match imm {
-                        Immediate::Bit8(value) => (value as u16, 1),
-                        Immediate::Bit16(value) => (value, 2),
-                    };
-                    match dest {
-                        Operand::Register(reg) => {
-                            let (dest_value, size, offset) = get_register_value(registers, reg);
-
-                            let diff = (dest_value as i16 - imm as i16) as u16;
-
-                            let diff_bytes = diff.to_ne_bytes();
-                            let end = (size + offset) as usize;
-                            let diff_slice = &diff_bytes[offset as usize..end];
-                            let dest = get_register_as_slice(registers, reg);
-                            dest.copy_from_slice(diff_slice);
-                            if size == 1 {
-                                set_flags(&mut registers.flags, diff, diff_slice[0], diff_slice[0]);
-                            } else {
-                                set_flags(&mut registers.flags, diff, diff_slice[0], diff_slice[1]);
-                            }
-                        }
-                        Operand::Memory(_) => unimplemented!(),
-                        Operand::Immediate(_) => unreachable!(),
-                    };
+            let zero = registers.flags & RegisterFile::ZF_MASK != 0;
+            let taken = registers.cx != 0
+                && match instruction.op {
+                    Op::Loopz => zero,
+                    Op::Loopnz => !zero,
+                    _ => true,
+                };
+            if taken {
+                relative_jump(registers, ip_inc);
+                cycles += if instruction.op == Op::Loopnz { 14 } else { 12 };
+            }
+        }
+        Op::Jcxz => {
+            let ip_inc = instruction.operands[0].expect("jcxz must have operand");
+            if registers.cx == 0 {
+                relative_jump(registers, ip_inc);
+                cycles += 12;
+            }
+        }
+        Op::Jmp => {
+            let target = instruction.operands[0].expect("jmp must have operand");
+            let (segment, offset) = branch_target(registers, memory, target);
+            if let Some(segment) = segment {
+                registers.cs = segment;
+            }
+            registers.ip = offset;
+        }
+        Op::Call => {
+            // The target is read before the return address is pushed.
+            let target = instruction.operands[0].expect("call must have operand");
+            let (segment, offset) = branch_target(registers, memory, target);
+            if let Some(segment) = segment {
+                push(registers, memory, registers.cs);
+                registers.cs = segment;
+            }
+            push(registers, memory, registers.ip);
+            registers.ip = offset;
+        }
+        Op::Ret | Op::Retf => {
+            registers.ip = pop(registers, memory);
+            if instruction.op == Op::Retf {
+                registers.cs = pop(registers, memory);
+            }
+            if let Some(Operand::Immediate(Immediate::Bit16(count))) = instruction.operands[0] {
+                registers.sp = registers.sp.wrapping_add(count);
+            }
+        }
+        Op::In => {
+            let dest = instruction.operands[0].expect("in must have operands");
+            let port = instruction.operands[1].expect("in must have operands");
+
+            let port = match port {
+                Operand::Immediate(Immediate::Bit8(port)) => port as u16,
+                Operand::Register(Register::DX) => registers.dx,
+                _ => unreachable!(),
+            };
+            match dest {
+                Operand::Register(Register::AL) => {
+                    let value = io.read8(port);
+                    registers.write(Register::AL, value as u16);
                 }
-                Operand::Memory(_) => unimplemented!(),
+                Operand::Register(Register::AX) => registers.ax = io.read16(port),
+                _ => unreachable!(),
             };
         }
-        Op::Cmp => {
-            let dest = instruction.operands[0].expect("cmp must have operands");
-            let src = instruction.operands[1].expect("cmp must have operands");
+        Op::Out => {
+            let port = instruction.operands[0].expect("out must have operands");
+            let src = instruction.operands[1].expect("out must have operands");
 
+            let port = match port {
+                Operand::Immediate(Immediate::Bit8(port)) => port as u16,
+                Operand::Register(Register::DX) => registers.dx,
+                _ => unreachable!(),
+            };
             match src {
-                Operand::Register(reg) => {
-                    let (src_value, _, offset) = get_register_value(registers, reg);
-
-                    match dest {
-                        Operand::Register(reg) => {
-                            let (dest_value, size, _) = get_register_value(registers, reg);
-                            let diff = (dest_value as i16 - src_value as i16) as u16;
-
-                            let diff_bytes = diff.to_ne_bytes();
-                            let end = (size + offset) as usize;
-                            let diff_slice = &diff_bytes[offset as usize..end];
-                            if size == 1 {
-                                set_flags(&mut registers.flags, diff, diff_slice[0], diff_slice[0]);
-                            } else {
-                                set_flags(&mut registers.flags, diff, diff_slice[0], diff_slice[1]);
-                            }
-                        }
-                        Operand::Memory(_) => unimplemented!(),
-                        Operand::Immediate(_) => unreachable!(),
-                    };
-                }
-                Operand::Immediate(imm) => {
-                    let (imm, _) = match imm {
-                        Immediate::Bit8(value) => (value as u16, 1),
-                        Immediate::Bit16(value) => (value, 2),
-                    };
-                    match dest {
-                        Operand::Register(reg) => {
-                            let (dest_value, size, offset) = get_register_value(registers, reg);
-
-                            let diff = (dest_value as i16 - imm as i16) as u16;
-
-                            let diff_bytes = diff.to_ne_bytes();
-                            let end = (size + offset) as usize;
-                            let diff_slice = &diff_bytes[offset as usize..end];
-                            if size == 1 {
-                                set_flags(&mut registers.flags, diff, diff_slice[0], diff_slice[0]);
-                            } else {
-                                set_flags(&mut registers.flags, diff, diff_slice[0], diff_slice[1]);
-                            }
-                        }
-                        Operand::Memory(_) => unimplemented!(),
-                        Operand::Immediate(_) => unreachable!(),
-                    };
-                }
-                Operand::Memory(_) => unimplemented!(),
+                Operand::Register(Register::AL) => io.write8(port, registers.ax as u8),
+                Operand::Register(Register::AX) => io.write16(port, registers.ax),
+                _ => unreachable!(),
             };
         }
-        Op::Jne => {
-            let ip_inc = instruction.operands[0].expect("jne must have operand");
-            let value = match ip_inc {
-                Operand::Immediate(Immediate::Bit8(imm)) => imm,
+        Op::Int => {
+            let vector = match instruction.operands[0] {
+                Some(Operand::Immediate(Immediate::Bit8(vector))) => vector,
                 _ => unreachable!(),
             };
+            match &mut io.disk {
+                Some(disk) if vector == 0x13 => disk.service(registers, memory),
+                _ => interrupt(registers, memory, vector),
+            }
+        }
+        Op::Int3 => interrupt(registers, memory, 3),
+        Op::Into => {
+            if registers.flags & RegisterFile::OF_MASK != 0 {
+                interrupt(registers, memory, 4);
+                cycles += 49;
+            }
+        }
+        Op::Iret => {
+            registers.ip = pop(registers, memory);
+            registers.cs = pop(registers, memory);
+            registers.flags = pop(registers, memory);
+        }
+        Op::Cli => registers.flags &= !RegisterFile::IF_MASK,
+        Op::Clc => registers.flags &= !RegisterFile::CF_MASK,
+        Op::Stc => registers.flags |= RegisterFile::CF_MASK,
+        Op::Cmc => registers.flags ^= RegisterFile::CF_MASK,
+        Op::Cld => registers.flags &= !RegisterFile::DF_MASK,
+        Op::Std => registers.flags |= RegisterFile::DF_MASK,
+        // There is no coprocessor to drive TEST, so WAIT falls straight through.
+        Op::Nop | Op::Wait => {}
+        // The 8086 has no invalid opcode trap; raise the one later parts
+        // use rather than guess at what the byte did.
+        Op::Db => interrupt(registers, memory, 6),
+        Op::Sti => {
+            registers.flags |= RegisterFile::IF_MASK;
+            registers.interrupt_shadow = true;
+        }
+        Op::Movsb
+        | Op::Movsw
+        | Op::Cmpsb
+        | Op::Cmpsw
+        | Op::Scasb
+        | Op::Scasw
+        | Op::Lodsb
+        | Op::Lodsw
+        | Op::Stosb
+        | Op::Stosw => cycles = simulate_string(registers, memory, instruction),
+        Op::Mul | Op::Imul | Op::Div | Op::Idiv => {
+            let src = instruction.operands[0].expect("mul/div must have operand");
+            let value = read_operand(registers, memory, src);
 
-            if registers.flags & RegisterFile::ZF_MASK != 0b1000 {
-                let test = registers.ip as i16 + value as i8 as i16;
-                registers.ip = test as u16;
+            if !multiply_divide(registers, instruction.op, value, operand_is_word(src)) {
+                interrupt(registers, memory, 0);
+                cycles += 51;
+            }
+        }
+        Op::Daa | Op::Das | Op::Aaa | Op::Aas | Op::Aam | Op::Aad => {
+            let base = match instruction.operands[0] {
+                Some(Operand::Immediate(Immediate::Bit8(base))) => base,
+                _ => 10,
+            };
+            if !decimal_adjust(registers, instruction.op, base) {
+                interrupt(registers, memory, 0);
+                cycles += 51;
             }
         }
-        _ => unimplemented!(),
+        Op::Inc | Op::Dec => {
+            let dest = instruction.operands[0].expect("inc/dec must have operand");
+            let value = read_operand(registers, memory, dest);
+            let word = operand_is_word(dest);
+
+            // INC and DEC leave CF alone.
+            let carry = registers.flags & RegisterFile::CF_MASK;
+            let result = if instruction.op == Op::Inc {
+                add_flags(&mut registers.flags, value, 1, 0, word)
+            } else {
+                sub_flags(&mut registers.flags, value, 1, 0, word)
+            };
+            registers.flags = (registers.flags & !RegisterFile::CF_MASK) | carry;
+            write_operand(registers, memory, dest, result);
+        }
+        Op::Xchg => {
+            let dest = instruction.operands[0].expect("xchg must have operands");
+            let src = instruction.operands[1].expect("xchg must have operands");
+            let dest_value = read_operand(registers, memory, dest);
+            let src_value = read_operand(registers, memory, src);
+            write_operand(registers, memory, dest, src_value);
+            write_operand(registers, memory, src, dest_value);
+        }
+        Op::Lea => {
+            let dest = instruction.operands[0].expect("lea must have operands");
+            let src = instruction.operands[1].expect("lea must have operands");
+            // LEA with a register source is undefined, it is treated as a no-op.
+            if let (Operand::Register(reg), Operand::Memory(mem)) = (dest, src) {
+                let offset = get_effective_address(registers, mem);
+                registers.write(reg, offset);
+            }
+        }
+        Op::Lds | Op::Les => {
+            let dest = instruction.operands[0].expect("lds/les must have operands");
+            let src = instruction.operands[1].expect("lds/les must have operands");
+            if let (Operand::Register(reg), Operand::Memory(mem)) = (dest, src) {
+                let (selector, value) = read_far_pointer(registers, memory, mem);
+                registers.write(reg, value);
+                if instruction.op == Op::Lds {
+                    registers.ds = selector;
+                } else {
+                    registers.es = selector;
+                }
+            }
+        }
+        Op::Cbw => registers.ax = registers.ax as u8 as i8 as i16 as u16,
+        Op::Cwd => {
+            registers.dx = if registers.ax & 0x8000 != 0 {
+                0xffff
+            } else {
+                0
+            }
+        }
+        Op::Xlat => {
+            let offset = registers.bx.wrapping_add(registers.ax & 0xff);
+            let value = memory.read8(physical_address(
+                data_segment(registers, &instruction),
+                offset,
+            ));
+            registers.write(Register::AL, value as u16);
+        }
+        Op::Lahf => {
+            // Bit 1 always reads as set, bits 3 and 5 as clear.
+            let value = registers.flags & 0xd5 | 0x02;
+            registers.write(Register::AH, value);
+        }
+        Op::Sahf => {
+            let value = (registers.ax >> 8) & 0xd5;
+            registers.flags = registers.flags & !0xd5 | value;
+        }
     }
-}
 
-fn u16_as_byte_slice(value: &u16, size: u8) -> &[u8] {
-    unsafe { std::slice::from_raw_parts(value as *const u16 as *const _, size as usize) }
+    if instruction.prefixes.segment.is_some() {
+        cycles += 2;
+    }
+    if instruction.prefixes.lock {
+        cycles += 2;
+    }
+    cycles
 }
 
-fn u16_as_byte_slice_mut(value: &mut u16, size: u8) -> &mut [u8] {
-    unsafe { std::slice::from_raw_parts_mut(value as *mut u16 as *mut _, size as usize) }
-}
+/// Runs a single iteration of a string instruction. While a REP prefix has
+/// iterations left IP is moved back onto the instruction, so each iteration
+/// is a separate step and interrupts can be taken in between.
+fn simulate_string(
+    registers: &mut RegisterFile,
+    memory: &mut dyn Bus,
+    instruction: Instruction,
+) -> u32 {
+    let op = instruction.op;
+    let word = matches!(
+        op,
+        Op::Movsw | Op::Cmpsw | Op::Scasw | Op::Lodsw | Op::Stosw
+    );
+    let size = if word { 2 } else { 1 };
+    let delta = if registers.flags & RegisterFile::DF_MASK != 0 {
+        0u16.wrapping_sub(size)
+    } else {
+        size
+    };
 
-fn set_flags(flags: &mut u16, value: u16, value_lo: u8, value_hi: u8) {
-    if value == 0 {
-        *flags |= RegisterFile::ZF_MASK;
+    let rep = instruction.prefixes.rep;
+    if rep.is_some() && registers.cx == 0 {
+        return 9;
+    }
+
+    // Only the source can be overridden, the destination is always ES:DI.
+    let src = (data_segment(registers, &instruction), registers.si);
+    let dest = (registers.es, registers.di);
+    let read = |memory: &mut dyn Bus, (segment, offset): (u16, u16)| {
+        if word {
+            read_word(memory, segment, offset)
+        } else {
+            memory.read8(physical_address(segment, offset)) as u16
+        }
+    };
+    let write = |memory: &mut dyn Bus, (segment, offset): (u16, u16), value: u16| {
+        if word {
+            write_word(memory, segment, offset, value)
+        } else {
+            memory.write8(physical_address(segment, offset), value as u8)
+        }
+    };
+    let acc = if word {
+        registers.ax
     } else {
-        *flags &= !RegisterFile::ZF_MASK;
+        registers.ax & 0xff
+    };
+
+    let (uses_si, uses_di) = match op {
+        Op::Movsb | Op::Movsw => {
+            let value = read(memory, src);
+            write(memory, dest, value);
+            (true, true)
+        }
+        Op::Cmpsb | Op::Cmpsw => {
+            let (a, b) = (read(memory, src), read(memory, dest));
+            sub_flags(&mut registers.flags, a, b, 0, word);
+            (true, true)
+        }
+        Op::Scasb | Op::Scasw => {
+            let b = read(memory, dest);
+            sub_flags(&mut registers.flags, acc, b, 0, word);
+            (false, true)
+        }
+        Op::Lodsb | Op::Lodsw => {
+            let value = read(memory, src);
+            let reg = if word { Register::AX } else { Register::AL };
+            registers.write(reg, value);
+            (true, false)
+        }
+        Op::Stosb | Op::Stosw => {
+            write(memory, dest, acc);
+            (false, true)
+        }
+        _ => unreachable!(),
+    };
+    if uses_si {
+        registers.si = registers.si.wrapping_add(delta);
+    }
+    if uses_di {
+        registers.di = registers.di.wrapping_add(delta);
     }
 
-    if value_hi & 0x80 == 0x80 {
-        *flags |= RegisterFile::SF_MASK;
+    let Some(rep) = rep else {
+        return base_cycles(&instruction);
+    };
+
+    let per_iteration = match op {
+        Op::Movsb | Op::Movsw => 17,
+        Op::Cmpsb | Op::Cmpsw => 22,
+        Op::Scasb | Op::Scasw => 15,
+        Op::Lodsb | Op::Lodsw => 13,
+        _ => 10,
+    };
+
+    registers.cx = registers.cx.wrapping_sub(1);
+    let zf = registers.flags & RegisterFile::ZF_MASK != 0;
+    let compares = matches!(op, Op::Cmpsb | Op::Cmpsw | Op::Scasb | Op::Scasw);
+    let done = registers.cx == 0
+        || (compares && rep == Rep::Rep && !zf)
+        || (compares && rep == Rep::Repne && zf);
+
+    if done {
+        9 + per_iteration
     } else {
-        *flags &= !RegisterFile::SF_MASK;
+        registers.ip = registers.ip.wrapping_sub(instruction.length as u16);
+        per_iteration
     }
 }
 
-fn get_register_as_slice(registers: &mut RegisterFile, reg: Register) -> &mut [u8] {
-    match reg {
-        Register::AL => {
-            let bytes = u16_as_byte_slice_mut(&mut registers.ax, 2);
-            &mut bytes[0..1]
+/// MUL, IMUL, DIV and IDIV on AL/AX (and AH/DX). Returns `false` instead of
+/// touching any register on a divide error: division by zero or a quotient
+/// that does not fit. As on the 8086, the most negative quotient (-128 or
+/// -32768) is an error for IDIV too. Flags the 8086 leaves undefined are not
+/// modified.
+fn multiply_divide(registers: &mut RegisterFile, op: Op, src: u16, word: bool) -> bool {
+    let overflow = match (op, word) {
+        (Op::Mul, false) => {
+            registers.ax = (registers.ax & 0xff) * (src & 0xff);
+            registers.ax > 0xff
         }
-        Register::CL => {
-            let bytes = u16_as_byte_slice_mut(&mut registers.cx, 2);
-            &mut bytes[0..1]
+        (Op::Mul, true) => {
+            let product = registers.ax as u32 * src as u32;
+            registers.ax = product as u16;
+            registers.dx = (product >> 16) as u16;
+            registers.dx != 0
         }
-        Register::DL => {
-            let bytes = u16_as_byte_slice_mut(&mut registers.dx, 2);
-            &mut bytes[0..1]
+        (Op::Imul, false) => {
+            let product = registers.ax as u8 as i8 as i16 * src as u8 as i8 as i16;
+            registers.ax = product as u16;
+            product != product as i8 as i16
         }
-        Register::BL => {
-            let bytes = u16_as_byte_slice_mut(&mut registers.bx, 2);
-            &mut bytes[0..1]
+        (Op::Imul, true) => {
+            let product = registers.ax as i16 as i32 * src as i16 as i32;
+            registers.ax = product as u16;
+            registers.dx = (product >> 16) as u16;
+            product != product as i16 as i32
         }
-        Register::AH => {
-            let bytes = u16_as_byte_slice_mut(&mut registers.ax, 2);
-            &mut bytes[1..]
+        (Op::Div, false) => {
+            let divisor = src & 0xff;
+            if divisor == 0 || registers.ax / divisor > 0xff {
+                return false;
+            }
+            let (quotient, remainder) = (registers.ax / divisor, registers.ax % divisor);
+            registers.ax = remainder << 8 | quotient;
+            return true;
         }
-        Register::CH => {
-            let bytes = u16_as_byte_slice_mut(&mut registers.cx, 2);
-            &mut bytes[1..]
+        (Op::Div, true) => {
+            let dividend = (registers.dx as u32) << 16 | registers.ax as u32;
+            let divisor = src as u32;
+            if divisor == 0 || dividend / divisor > 0xffff {
+                return false;
+            }
+            registers.ax = (dividend / divisor) as u16;
+            registers.dx = (dividend % divisor) as u16;
+            return true;
         }
-        Register::DH => {
-            let bytes = u16_as_byte_slice_mut(&mut registers.dx, 2);
-            &mut bytes[1..]
+        (Op::Idiv, false) => {
+            let dividend = registers.ax as i16 as i32;
+            let divisor = src as u8 as i8 as i32;
+            if divisor == 0 || !(-127..=127).contains(&(dividend / divisor)) {
+                return false;
+            }
+            let (quotient, remainder) = (dividend / divisor, dividend % divisor);
+            registers.ax = (remainder as u8 as u16) << 8 | quotient as u8 as u16;
+            return true;
         }
-        Register::BH => {
-            let bytes = u16_as_byte_slice_mut(&mut registers.bx, 2);
-            &mut bytes[1..]
+        (Op::Idiv, true) => {
+            let dividend = ((registers.dx as u32) << 16 | registers.ax as u32) as i32 as i64;
+            let divisor = src as i16 as i64;
+            if divisor == 0 || !(-32767..=32767).contains(&(dividend / divisor)) {
+                return false;
+            }
+            registers.ax = (dividend / divisor) as u16;
+            registers.dx = (dividend % divisor) as u16;
+            return true;
         }
+        _ => unreachable!(),
+    };
+
+    set_flag(&mut registers.flags, RegisterFile::CF_MASK, overflow);
+    set_flag(&mut registers.flags, RegisterFile::OF_MASK, overflow);
+    true
+}
 
-        Register::AX => u16_as_byte_slice_mut(&mut registers.ax, 2),
-        Register::CX => u16_as_byte_slice_mut(&mut registers.cx, 2),
-        Register::DX => u16_as_byte_slice_mut(&mut registers.dx, 2),
-        Register::BX => u16_as_byte_slice_mut(&mut registers.bx, 2),
-        Register::SP => u16_as_byte_slice_mut(&mut registers.sp, 2),
-        Register::BP => u16_as_byte_slice_mut(&mut registers.bp, 2),
-        Register::SI => u16_as_byte_slice_mut(&mut registers.si, 2),
-        Register::DI => u16_as_byte_slice_mut(&mut registers.di, 2),
+/// DAA, DAS, AAA, AAS, AAM and AAD as the 8086 implements them, including
+/// the flags Intel leaves undefined: DAA/DAS compare against 9Fh instead of
+/// 99h when AF was already set, AAA/AAS only carry into AH (no 106h
+/// adjustment of AX) and set SF/ZF/PF/OF from the intermediate AL, and AAD
+/// sets its flags like the ADD it performs. Returns `false` on AAM with a
+/// zero base, which is a divide error.
+fn decimal_adjust(registers: &mut RegisterFile, op: Op, base: u8) -> bool {
+    let al = registers.ax as u8;
+    let ah = (registers.ax >> 8) as u8;
+    let af = registers.flags & RegisterFile::AF_MASK != 0;
+    let cf = registers.flags & RegisterFile::CF_MASK != 0;
+    let flags = &mut registers.flags;
+
+    let (al, ah) = match op {
+        Op::Daa | Op::Das => {
+            let limit = if af { 0x9f } else { 0x99 };
+            let low = (al & 0x0f) > 9 || af;
+            let high = al > limit || cf;
+            let mut result = al;
+            if low {
+                result = if op == Op::Daa {
+                    result.wrapping_add(0x06)
+                } else {
+                    result.wrapping_sub(0x06)
+                };
+            }
+            if high {
+                result = if op == Op::Daa {
+                    result.wrapping_add(0x60)
+                } else {
+                    result.wrapping_sub(0x60)
+                };
+            }
+
+            let overflow = if op == Op::Daa {
+                al & 0x80 == 0 && result & 0x80 != 0
+            } else {
+                al & 0x80 != 0 && result & 0x80 == 0
+            };
+            set_flag(flags, RegisterFile::AF_MASK, low);
+            set_flag(flags, RegisterFile::CF_MASK, high);
+            set_flag(flags, RegisterFile::OF_MASK, overflow);
+            szp_flags(flags, result as u16, false);
+            (result, ah)
+        }
+        Op::Aaa | Op::Aas => {
+            let adjust = (al & 0x0f) > 9 || af;
+            let (adjustment, ah) = match (op, adjust) {
+                (Op::Aaa, true) => (0x06, ah.wrapping_add(1)),
+                (Op::Aas, true) => (0x06, ah.wrapping_sub(1)),
+                _ => (0x00, ah),
+            };
+            let result = if op == Op::Aaa {
+                add_flags(flags, al as u16, adjustment, 0, false)
+            } else {
+                sub_flags(flags, al as u16, adjustment, 0, false)
+            };
+            set_flag(flags, RegisterFile::AF_MASK, adjust);
+            set_flag(flags, RegisterFile::CF_MASK, adjust);
+            (result as u8 & 0x0f, ah)
+        }
+        Op::Aam => {
+            if base == 0 {
+                return false;
+            }
+            let (ah, al) = (al / base, al % base);
+            logic_flags(flags, al as u16, false);
+            (al, ah)
+        }
+        Op::Aad => {
+            let product = ah.wrapping_mul(base);
+            let result = add_flags(flags, al as u16, product as u16, 0, false);
+            (result as u8, 0)
+        }
+        _ => unreachable!(),
+    };
+
+    registers.ax = (ah as u16) << 8 | al as u16;
+    true
+}
+
+/// Computes `dest - src - borrow` for a byte or word operand and updates the
+/// arithmetic flags accordingly.
+fn sub_flags(flags: &mut u16, dest: u16, src: u16, borrow: u16, word: bool) -> u16 {
+    let (mask, sign) = if word { (0xffff, 0x8000) } else { (0xff, 0x80) };
+    let (dest, src) = (dest as u32 & mask, src as u32 & mask);
+    let full = dest.wrapping_sub(src).wrapping_sub(borrow as u32);
+    let result = full & mask;
+
+    set_flag(flags, RegisterFile::CF_MASK, dest < src + borrow as u32);
+    set_flag(
+        flags,
+        RegisterFile::AF_MASK,
+        (dest ^ src ^ result) & 0x10 != 0,
+    );
+    set_flag(
+        flags,
+        RegisterFile::OF_MASK,
+        (dest ^ src) & (dest ^ result) & sign != 0,
+    );
+    szp_flags(flags, result as u16, word);
+    result as u16
+}
+
+/// Computes `dest + src + carry` for a byte or word operand and updates the
+/// arithmetic flags accordingly.
+fn add_flags(flags: &mut u16, dest: u16, src: u16, carry: u16, word: bool) -> u16 {
+    let (mask, sign) = if word { (0xffff, 0x8000) } else { (0xff, 0x80) };
+    let (dest, src) = (dest as u32 & mask, src as u32 & mask);
+    let full = dest + src + carry as u32;
+    let result = full & mask;
+
+    set_flag(flags, RegisterFile::CF_MASK, full > mask);
+    set_flag(
+        flags,
+        RegisterFile::AF_MASK,
+        (dest ^ src ^ result) & 0x10 != 0,
+    );
+    set_flag(
+        flags,
+        RegisterFile::OF_MASK,
+        !(dest ^ src) & (dest ^ result) & sign != 0,
+    );
+    szp_flags(flags, result as u16, word);
+    result as u16
+}
+
+/// Flags for AND, OR, XOR and TEST: CF, OF and AF are cleared.
+fn logic_flags(flags: &mut u16, result: u16, word: bool) -> u16 {
+    *flags &= !(RegisterFile::CF_MASK | RegisterFile::OF_MASK | RegisterFile::AF_MASK);
+    szp_flags(flags, result, word);
+    result
+}
+
+/// Shifts or rotates `value` one bit at a time, `count` times, like the
+/// 8086 does (the count is not masked). A zero count leaves the flags
+/// alone; rotates only touch CF and OF.
+fn shift_rotate(flags: &mut u16, op: Op, value: u16, count: u8, word: bool) -> u16 {
+    let (mask, sign) = if word { (0xffff, 0x8000) } else { (0xff, 0x80) };
+    let mut value = value & mask;
+    let mut cf = *flags & RegisterFile::CF_MASK != 0;
+    if count == 0 {
+        return value;
+    }
+
+    for _ in 0..count {
+        let msb = value & sign != 0;
+        let lsb = value & 1 != 0;
+        value = match op {
+            Op::Shl => (value << 1) & mask,
+            Op::Shr => value >> 1,
+            Op::Sar => (value >> 1) | (value & sign),
+            Op::Rol => ((value << 1) | msb as u16) & mask,
+            Op::Ror => (value >> 1) | if lsb { sign } else { 0 },
+            Op::Rcl => ((value << 1) | cf as u16) & mask,
+            Op::Rcr => (value >> 1) | if cf { sign } else { 0 },
+            _ => unreachable!(),
+        };
+        cf = match op {
+            Op::Shl | Op::Rol | Op::Rcl => msb,
+            _ => lsb,
+        };
     }
+
+    let msb = value & sign != 0;
+    let overflow = match op {
+        Op::Shl | Op::Rol | Op::Rcl => msb ^ cf,
+        _ => msb ^ (value & (sign >> 1) != 0),
+    };
+    set_flag(flags, RegisterFile::CF_MASK, cf);
+    set_flag(flags, RegisterFile::OF_MASK, overflow);
+    if matches!(op, Op::Shl | Op::Shr | Op::Sar) {
+        szp_flags(flags, value, word);
+    }
+    value
 }
 
-fn get_register_value(registers: &mut RegisterFile, reg: Register) -> (u16, u8, u8) {
-    match reg {
-        Register::AL => (registers.ax, 1, 0),
-        Register::CL => (registers.cx, 1, 0),
-        Register::DL => (registers.dx, 1, 0),
-        Register::BL => (registers.bx, 1, 0),
-        Register::AH => (registers.ax, 1, 1),
-        Register::CH => (registers.cx, 1, 1),
-        Register::DH => (registers.dx, 1, 1),
-        Register::BH => (registers.bx, 1, 1),
+/// Sets ZF, SF and PF from a byte or word result.
+fn szp_flags(flags: &mut u16, result: u16, word: bool) {
+    let (mask, sign) = if word { (0xffff, 0x8000) } else { (0xff, 0x80) };
+    set_flag(flags, RegisterFile::ZF_MASK, result & mask == 0);
+    set_flag(flags, RegisterFile::SF_MASK, result & sign != 0);
+    set_flag(
+        flags,
+        RegisterFile::PF_MASK,
+        (result as u8).count_ones().is_multiple_of(2),
+    );
+}
 
-        Register::AX => (registers.ax, 2, 0),
-        Register::CX => (registers.cx, 2, 0),
-        Register::DX => (registers.dx, 2, 0),
-        Register::BX => (registers.bx, 2, 0),
-        Register::SP => (registers.sp, 2, 0),
-        Register::BP => (registers.bp, 2, 0),
-        Register::SI => (registers.si, 2, 0),
-        Register::DI => (registers.di, 2, 0),
+fn set_flag(flags: &mut u16, mask: u16, value: bool) {
+    if value {
+        *flags |= mask;
+    } else {
+        *flags &= !mask;
     }
 }
 
-fn get_address_from_operand(register_file: &RegisterFile, memory_operand: MemoryOperand) -> usize {
-    let addr = match memory_operand.kind {
-        MemoryOperandKind::Direct_BX_SI => register_file.bx + register_file.si,
-        MemoryOperandKind::Direct_BX_DI => register_file.bx + register_file.di,
-        MemoryOperandKind::Direct_BP_SI => register_file.bp + register_file.si,
-        MemoryOperandKind::Direct_BP_DI => register_file.bp + register_file.di,
-        MemoryOperandKind::Direct_SI => register_file.si,
-        MemoryOperandKind::Direct_DI => register_file.di,
-        MemoryOperandKind::Direct_Address(addr) => addr,
-        MemoryOperandKind::Direct_BX => register_file.bx,
+fn prefix_count(prefixes: &Prefixes) -> u8 {
+    prefixes.segment.is_some() as u8 + prefixes.lock as u8 + prefixes.rep.is_some() as u8
+}
 
-        MemoryOperandKind::Disp8_BX_SI(disp) => {
-            ((register_file.bx + register_file.si) as i16 + disp as i16) as u16
+/// Clock count of `instruction` on an 8086, not counting the extra time a
+/// taken branch needs. Odd-address word transfer penalties and wait states
+/// are not modelled.
+fn base_cycles(instruction: &Instruction) -> u32 {
+    let operands = (instruction.operands[0], instruction.operands[1]);
+    match instruction.op {
+        Op::Mov => match operands {
+            (Some(Operand::Register(_)), Some(Operand::Register(_))) => 2,
+            (Some(Operand::Register(_)), Some(Operand::Immediate(_))) => 4,
+            (Some(Operand::Memory(mem)), Some(Operand::Immediate(_))) => 10 + ea_cycles(mem),
+            (
+                Some(Operand::Register(Register::AL | Register::AX)),
+                Some(Operand::Memory(MemoryOperand {
+                    kind: MemoryOperandKind::Direct_Address(_),
+                    ..
+                })),
+            )
+            | (
+                Some(Operand::Memory(MemoryOperand {
+                    kind: MemoryOperandKind::Direct_Address(_),
+                    ..
+                })),
+                Some(Operand::Register(Register::AL | Register::AX)),
+            ) => 10,
+            (Some(Operand::Register(_)), Some(Operand::Memory(mem))) => 8 + ea_cycles(mem),
+            (Some(Operand::Memory(mem)), Some(Operand::Register(_))) => 9 + ea_cycles(mem),
+            _ => unreachable!(),
+        },
+        Op::Add | Op::Adc | Op::Sub | Op::Sbb | Op::Cmp | Op::And | Op::Or | Op::Xor => {
+            match operands {
+                (Some(Operand::Register(_)), Some(Operand::Register(_))) => 3,
+                (Some(Operand::Register(_)), Some(Operand::Immediate(_))) => 4,
+                (Some(Operand::Register(_)), Some(Operand::Memory(mem))) => 9 + ea_cycles(mem),
+                (Some(Operand::Memory(mem)), _) if instruction.op == Op::Cmp => {
+                    let base = if let Some(Operand::Immediate(_)) = operands.1 {
+                        10
+                    } else {
+                        9
+                    };
+                    base + ea_cycles(mem)
+                }
+                (Some(Operand::Memory(mem)), Some(Operand::Immediate(_))) => 17 + ea_cycles(mem),
+                (Some(Operand::Memory(mem)), Some(Operand::Register(_))) => 16 + ea_cycles(mem),
+                _ => unreachable!(),
+            }
         }
-        MemoryOperandKind::Disp8_BX_DI(disp) => {
-            ((register_file.bx + register_file.di) as i16 + disp as i16) as u16
+        Op::Test => match operands {
+            (Some(Operand::Register(_)), Some(Operand::Register(_))) => 3,
+            (Some(Operand::Register(Register::AL | Register::AX)), Some(Operand::Immediate(_))) => {
+                4
+            }
+            (Some(Operand::Register(_)), Some(Operand::Immediate(_))) => 5,
+            (Some(Operand::Memory(mem)), Some(Operand::Immediate(_))) => 11 + ea_cycles(mem),
+            (Some(Operand::Memory(mem)), _) | (_, Some(Operand::Memory(mem))) => 9 + ea_cycles(mem),
+            _ => unreachable!(),
+        },
+        Op::Not | Op::Neg => match operands.0 {
+            Some(Operand::Memory(mem)) => 16 + ea_cycles(mem),
+            _ => 3,
+        },
+        // The per-bit time of shifts by CL is added when they execute.
+        Op::Shl | Op::Shr | Op::Sar | Op::Rol | Op::Ror | Op::Rcl | Op::Rcr => match operands {
+            (Some(Operand::Memory(mem)), Some(Operand::Register(_))) => 20 + ea_cycles(mem),
+            (Some(Operand::Memory(mem)), _) => 15 + ea_cycles(mem),
+            (_, Some(Operand::Register(_))) => 8,
+            _ => 2,
+        },
+        Op::Je
+        | Op::Jl
+        | Op::Jle
+        | Op::Jb
+        | Op::Jbe
+        | Op::Jp
+        | Op::Jo
+        | Op::Js
+        | Op::Jne
+        | Op::Jnl
+        | Op::Jg
+        | Op::Jnb
+        | Op::Ja
+        | Op::Jnp
+        | Op::Jno
+        | Op::Jns => 4,
+        Op::Loop | Op::Loopnz => 5,
+        Op::Loopz | Op::Jcxz => 6,
+        Op::Jmp => match operands.0 {
+            Some(Operand::Memory(mem)) if mem.size == MemoryOperandSize::Far => 24 + ea_cycles(mem),
+            Some(Operand::Memory(mem)) => 18 + ea_cycles(mem),
+            Some(Operand::Register(_)) => 11,
+            _ => 15,
+        },
+        Op::Call => match operands.0 {
+            Some(Operand::Memory(mem)) if mem.size == MemoryOperandSize::Far => 37 + ea_cycles(mem),
+            Some(Operand::Memory(mem)) => 21 + ea_cycles(mem),
+            Some(Operand::Register(_)) => 16,
+            Some(Operand::Pointer { .. }) => 28,
+            _ => 19,
+        },
+        Op::Ret => match operands.0 {
+            Some(_) => 12,
+            None => 8,
+        },
+        Op::Retf => match operands.0 {
+            Some(_) => 17,
+            None => 18,
+        },
+        Op::In | Op::Out => match operands {
+            (Some(Operand::Immediate(_)), _) | (_, Some(Operand::Immediate(_))) => 10,
+            _ => 8,
+        },
+        Op::Int | Op::Db => 51,
+        Op::Int3 => 52,
+        Op::Into => 4,
+        Op::Iret => 24,
+        Op::Cli | Op::Sti | Op::Clc | Op::Stc | Op::Cmc | Op::Cld | Op::Std => 2,
+        Op::Nop | Op::Wait => 3,
+        Op::Daa | Op::Das | Op::Aaa | Op::Aas => 4,
+        Op::Aam => 83,
+        Op::Aad => 60,
+        Op::Inc | Op::Dec => match operands.0 {
+            Some(Operand::Memory(mem)) => 15 + ea_cycles(mem),
+            Some(operand) if operand_is_word(operand) => 2,
+            _ => 3,
+        },
+        Op::Xchg => match operands {
+            (Some(Operand::Memory(mem)), _) | (_, Some(Operand::Memory(mem))) => {
+                17 + ea_cycles(mem)
+            }
+            // 90h-97h, one byte past its prefixes against two for the
+            // mod-reg-r/m form. A repeated prefix counts once here.
+            (Some(Operand::Register(Register::AX)), Some(Operand::Register(_)))
+                if instruction.length - prefix_count(&instruction.prefixes) == 1 =>
+            {
+                3
+            }
+            _ => 4,
+        },
+        Op::Lea => match operands.1 {
+            Some(Operand::Memory(mem)) => 2 + ea_cycles(mem),
+            _ => 2,
+        },
+        Op::Lds | Op::Les => match operands.1 {
+            Some(Operand::Memory(mem)) => 16 + ea_cycles(mem),
+            _ => 16,
+        },
+        Op::Cbw => 2,
+        Op::Cwd => 5,
+        Op::Xlat => 11,
+        Op::Lahf | Op::Sahf => 4,
+        Op::Movsb | Op::Movsw => 18,
+        Op::Cmpsb | Op::Cmpsw => 22,
+        Op::Scasb | Op::Scasw => 15,
+        Op::Lodsb | Op::Lodsw => 12,
+        Op::Stosb | Op::Stosw => 11,
+        // Multiply and divide timings are data dependent, these are the
+        // fastest cases.
+        Op::Mul | Op::Imul | Op::Div | Op::Idiv => {
+            let operand = operands.0.expect("mul/div must have operand");
+            let (byte, word) = match instruction.op {
+                Op::Mul => (70, 118),
+                Op::Imul => (80, 128),
+                Op::Div => (80, 144),
+                _ => (101, 165),
+            };
+            let base = if operand_is_word(operand) { word } else { byte };
+            match operand {
+                Operand::Memory(mem) => base + 6 + ea_cycles(mem),
+                _ => base,
+            }
         }
-        MemoryOperandKind::Disp8_BP_SI(disp) => {
-            ((register_file.bp + register_file.si) as i16 + disp as i16) as u16
+    }
+}
+
+/// Effective address calculation time.
+fn ea_cycles(memory_operand: MemoryOperand) -> u32 {
+    match memory_operand.kind {
+        MemoryOperandKind::Direct_SI
+        | MemoryOperandKind::Direct_DI
+        | MemoryOperandKind::Direct_BX => 5,
+        MemoryOperandKind::Direct_Address(_) => 6,
+        MemoryOperandKind::Direct_BP_DI | MemoryOperandKind::Direct_BX_SI => 7,
+        MemoryOperandKind::Direct_BP_SI | MemoryOperandKind::Direct_BX_DI => 8,
+        MemoryOperandKind::Disp8_SI(_)
+        | MemoryOperandKind::Disp8_DI(_)
+        | MemoryOperandKind::Disp8_BP(_)
+        | MemoryOperandKind::Disp8_BX(_)
+        | MemoryOperandKind::Disp16_SI(_)
+        | MemoryOperandKind::Disp16_DI(_)
+        | MemoryOperandKind::Disp16_BP(_)
+        | MemoryOperandKind::Disp16_BX(_) => 9,
+        MemoryOperandKind::Disp8_BP_DI(_)
+        | MemoryOperandKind::Disp8_BX_SI(_)
+        | MemoryOperandKind::Disp16_BP_DI(_)
+        | MemoryOperandKind::Disp16_BX_SI(_) => 11,
+        MemoryOperandKind::Disp8_BP_SI(_)
+        | MemoryOperandKind::Disp8_BX_DI(_)
+        | MemoryOperandKind::Disp16_BP_SI(_)
+        | MemoryOperandKind::Disp16_BX_DI(_) => 12,
+    }
+}
+
+/// Accepts a pending maskable interrupt from the PIC, if the CPU is able to
+/// take one between instructions. Returns `true` when control was
+/// transferred to an interrupt handler.
+pub fn accept_interrupt(
+    registers: &mut RegisterFile,
+    memory: &mut dyn Bus,
+    io: &mut IoBus,
+) -> bool {
+    if registers.flags & RegisterFile::IF_MASK == 0 || registers.interrupt_shadow {
+        return false;
+    }
+
+    match io.pic.acknowledge() {
+        Some(vector) => {
+            memory.set_instruction(registers.cs, registers.ip);
+            interrupt(registers, memory, vector);
+            true
         }
-        MemoryOperandKind::Disp8_BP_DI(disp) => {
-            ((register_file.bp + register_file.di) as i16 + disp as i16) as u16
+        None => false,
+    }
+}
+
+fn interrupt(registers: &mut RegisterFile, memory: &mut dyn Bus, vector: u8) {
+    push(registers, memory, registers.flags);
+    registers.flags &= !(RegisterFile::IF_MASK | RegisterFile::TF_MASK);
+    push(registers, memory, registers.cs);
+    push(registers, memory, registers.ip);
+
+    let entry = vector as usize * 4;
+    registers.ip = memory.read16(entry);
+    registers.cs = memory.read16(entry + 2);
+}
+
+fn condition(flags: u16, op: Op) -> bool {
+    let carry = flags & RegisterFile::CF_MASK != 0;
+    let parity = flags & RegisterFile::PF_MASK != 0;
+    let zero = flags & RegisterFile::ZF_MASK != 0;
+    let sign = flags & RegisterFile::SF_MASK != 0;
+    let overflow = flags & RegisterFile::OF_MASK != 0;
+
+    match op {
+        Op::Je => zero,
+        Op::Jne => !zero,
+        Op::Jl => sign != overflow,
+        Op::Jnl => sign == overflow,
+        Op::Jle => zero || sign != overflow,
+        Op::Jg => !zero && sign == overflow,
+        Op::Jb => carry,
+        Op::Jnb => !carry,
+        Op::Jbe => carry || zero,
+        Op::Ja => !carry && !zero,
+        Op::Jp => parity,
+        Op::Jnp => !parity,
+        Op::Jo => overflow,
+        Op::Jno => !overflow,
+        Op::Js => sign,
+        Op::Jns => !sign,
+        _ => unreachable!(),
+    }
+}
+
+fn relative_jump(registers: &mut RegisterFile, ip_inc: Operand) {
+    let disp = match ip_inc {
+        Operand::Immediate(Immediate::Bit8(disp)) => disp as i8 as u16,
+        Operand::Immediate(Immediate::Bit16(disp)) => disp,
+        _ => unreachable!(),
+    };
+    registers.ip = registers.ip.wrapping_add(disp);
+}
+
+/// Resolves a JMP or CALL operand to the new IP, along with the new CS for
+/// intersegment transfers.
+fn branch_target(
+    registers: &RegisterFile,
+    memory: &mut dyn Bus,
+    target: Operand,
+) -> (Option<u16>, u16) {
+    match target {
+        Operand::Immediate(_) => {
+            let mut next = *registers;
+            relative_jump(&mut next, target);
+            (None, next.ip)
+        }
+        Operand::Pointer { segment, offset } => (Some(segment), offset),
+        Operand::Memory(mem) if mem.size == MemoryOperandSize::Far => {
+            let (segment, offset) = read_far_pointer(registers, memory, mem);
+            (Some(segment), offset)
         }
-        MemoryOperandKind::Disp8_SI(disp) => (register_file.si as i16 + disp as i16) as u16,
-        MemoryOperandKind::Disp8_DI(disp) => (register_file.di as i16 + disp as i16) as u16,
-        MemoryOperandKind::Disp8_BP(disp) => (register_file.bp as i16 + disp as i16) as u16,
-        MemoryOperandKind::Disp8_BX(disp) => (register_file.bx as i16 + disp as i16) as u16,
+        _ => (None, read_operand(registers, memory, target)),
+    }
+}
+
+/// Reads an offset followed by a segment, returned as (segment, offset).
+fn read_far_pointer(
+    registers: &RegisterFile,
+    memory: &mut dyn Bus,
+    mem: MemoryOperand,
+) -> (u16, u16) {
+    let segment = get_segment_from_operand(registers, mem);
+    let offset = get_effective_address(registers, mem);
+    let value = read_word(memory, segment, offset);
+    let selector = read_word(memory, segment, offset.wrapping_add(2));
+    (selector, value)
+}
+
+fn push(registers: &mut RegisterFile, memory: &mut dyn Bus, value: u16) {
+    registers.sp = registers.sp.wrapping_sub(2);
+    write_word(memory, registers.ss, registers.sp, value);
+}
 
-        MemoryOperandKind::Disp16_BX_SI(disp) => {
-            ((register_file.bx + register_file.si) as i16 + disp) as u16
+fn pop(registers: &mut RegisterFile, memory: &mut dyn Bus) -> u16 {
+    let value = read_word(memory, registers.ss, registers.sp);
+    registers.sp = registers.sp.wrapping_add(2);
+    value
+}
+
+/// Segment times 16 plus offset, wrapped at 1 MiB as on the 8086's 20
+/// address lines.
+pub(crate) fn physical_address(segment: u16, offset: u16) -> usize {
+    (((segment as usize) << 4) + offset as usize) & 0xfffff
+}
+
+/// Reads the word at `segment:offset`. The high byte is at the next offset
+/// within the segment, so a word at offset FFFFh takes it from offset 0.
+fn read_word(memory: &mut dyn Bus, segment: u16, offset: u16) -> u16 {
+    if offset == 0xffff {
+        let lo = memory.read8(physical_address(segment, offset));
+        let hi = memory.read8(physical_address(segment, 0));
+        u16::from_le_bytes([lo, hi])
+    } else {
+        memory.read16(physical_address(segment, offset))
+    }
+}
+
+fn write_word(memory: &mut dyn Bus, segment: u16, offset: u16, value: u16) {
+    if offset == 0xffff {
+        let [lo, hi] = value.to_le_bytes();
+        memory.write8(physical_address(segment, offset), lo);
+        memory.write8(physical_address(segment, 0), hi);
+    } else {
+        memory.write16(physical_address(segment, offset), value)
+    }
+}
+
+fn operand_is_word(operand: Operand) -> bool {
+    match operand {
+        Operand::Register(reg) => reg.width() == RegisterWidth::Word,
+        Operand::Memory(mem) => mem.size == MemoryOperandSize::Word,
+        Operand::Immediate(imm) => matches!(imm, Immediate::Bit16(_)),
+        Operand::Pointer { .. } => unreachable!(),
+    }
+}
+
+fn read_operand(registers: &RegisterFile, memory: &mut dyn Bus, operand: Operand) -> u16 {
+    match operand {
+        Operand::Register(reg) => registers.read(reg),
+        Operand::Memory(mem) => {
+            let (segment, offset) = operand_address(registers, mem);
+            match mem.size {
+                MemoryOperandSize::Byte => memory.read8(physical_address(segment, offset)) as u16,
+                MemoryOperandSize::Word => read_word(memory, segment, offset),
+                MemoryOperandSize::Far => unreachable!(),
+            }
+        }
+        Operand::Immediate(Immediate::Bit8(value)) => value as u16,
+        Operand::Immediate(Immediate::Bit16(value)) => value,
+        Operand::Pointer { .. } => unreachable!(),
+    }
+}
+
+fn write_operand(registers: &mut RegisterFile, memory: &mut dyn Bus, operand: Operand, value: u16) {
+    match operand {
+        Operand::Register(reg) => registers.write(reg, value),
+        Operand::Memory(mem) => {
+            let (segment, offset) = operand_address(registers, mem);
+            match mem.size {
+                MemoryOperandSize::Byte => {
+                    memory.write8(physical_address(segment, offset), value as u8)
+                }
+                MemoryOperandSize::Word => write_word(memory, segment, offset, value),
+                MemoryOperandSize::Far => unreachable!(),
+            }
         }
-        MemoryOperandKind::Disp16_BX_DI(disp) => {
-            ((register_file.bx + register_file.di) as i16 + disp) as u16
-        }
-        MemoryOperandKind::Disp16_BP_SI(disp) => {
-            ((register_file.bp + register_file.si) as i16 + disp) as u16
+        Operand::Immediate(_) | Operand::Pointer { .. } => unreachable!(),
+    }
+}
+
+fn operand_address(register_file: &RegisterFile, memory_operand: MemoryOperand) -> (u16, u16) {
+    let segment = get_segment_from_operand(register_file, memory_operand);
+    let offset = get_effective_address(register_file, memory_operand);
+    (segment, offset)
+}
+
+fn get_segment_from_operand(register_file: &RegisterFile, memory_operand: MemoryOperand) -> u16 {
+    if let Some(segment) = memory_operand.segment {
+        return read_segment(register_file, segment);
+    }
+
+    match memory_operand.kind {
+        MemoryOperandKind::Direct_BP_SI
+        | MemoryOperandKind::Direct_BP_DI
+        | MemoryOperandKind::Disp8_BP_SI(_)
+        | MemoryOperandKind::Disp8_BP_DI(_)
+        | MemoryOperandKind::Disp8_BP(_)
+        | MemoryOperandKind::Disp16_BP_SI(_)
+        | MemoryOperandKind::Disp16_BP_DI(_)
+        | MemoryOperandKind::Disp16_BP(_) => register_file.ss,
+        _ => register_file.ds,
+    }
+}
+
+fn read_segment(register_file: &RegisterFile, segment: SegmentRegister) -> u16 {
+    match segment {
+        SegmentRegister::ES => register_file.es,
+        SegmentRegister::CS => register_file.cs,
+        SegmentRegister::SS => register_file.ss,
+        SegmentRegister::DS => register_file.ds,
+    }
+}
+
+/// DS, unless the instruction carries a segment override.
+fn data_segment(registers: &RegisterFile, instruction: &Instruction) -> u16 {
+    match instruction.prefixes.segment {
+        Some(segment) => read_segment(registers, segment),
+        None => registers.ds,
+    }
+}
+
+/// Offset of a memory operand. The sum is computed modulo 64K, so
+/// `[bx+si+disp]` past FFFFh wraps around to the start of the segment.
+fn get_effective_address(register_file: &RegisterFile, memory_operand: MemoryOperand) -> u16 {
+    let RegisterFile { bx, bp, si, di, .. } = *register_file;
+    let (base, disp) = match memory_operand.kind {
+        MemoryOperandKind::Direct_BX_SI => (bx.wrapping_add(si), 0),
+        MemoryOperandKind::Direct_BX_DI => (bx.wrapping_add(di), 0),
+        MemoryOperandKind::Direct_BP_SI => (bp.wrapping_add(si), 0),
+        MemoryOperandKind::Direct_BP_DI => (bp.wrapping_add(di), 0),
+        MemoryOperandKind::Direct_SI => (si, 0),
+        MemoryOperandKind::Direct_DI => (di, 0),
+        MemoryOperandKind::Direct_Address(addr) => (addr, 0),
+        MemoryOperandKind::Direct_BX => (bx, 0),
+
+        MemoryOperandKind::Disp8_BX_SI(disp) => (bx.wrapping_add(si), disp as i16),
+        MemoryOperandKind::Disp8_BX_DI(disp) => (bx.wrapping_add(di), disp as i16),
+        MemoryOperandKind::Disp8_BP_SI(disp) => (bp.wrapping_add(si), disp as i16),
+        MemoryOperandKind::Disp8_BP_DI(disp) => (bp.wrapping_add(di), disp as i16),
+        MemoryOperandKind::Disp8_SI(disp) => (si, disp as i16),
+        MemoryOperandKind::Disp8_DI(disp) => (di, disp as i16),
+        MemoryOperandKind::Disp8_BP(disp) => (bp, disp as i16),
+        MemoryOperandKind::Disp8_BX(disp) => (bx, disp as i16),
+
+        MemoryOperandKind::Disp16_BX_SI(disp) => (bx.wrapping_add(si), disp),
+        MemoryOperandKind::Disp16_BX_DI(disp) => (bx.wrapping_add(di), disp),
+        MemoryOperandKind::Disp16_BP_SI(disp) => (bp.wrapping_add(si), disp),
+        MemoryOperandKind::Disp16_BP_DI(disp) => (bp.wrapping_add(di), disp),
+        MemoryOperandKind::Disp16_SI(disp) => (si, disp),
+        MemoryOperandKind::Disp16_DI(disp) => (di, disp),
+        MemoryOperandKind::Disp16_BP(disp) => (bp, disp),
+        MemoryOperandKind::Disp16_BX(disp) => (bx, disp),
+    };
+    base.wrapping_add(disp as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> (RegisterFile, Memory, IoBus) {
+        let mut memory = Memory::default();
+        // IRQ0 -> vector 08h -> 1234:0010
+        memory[0x20..0x24].copy_from_slice(&[0x10, 0x00, 0x34, 0x12]);
+        let registers = RegisterFile {
+            sp: 0x100,
+            cs: 0x0050,
+            ip: 0x0007,
+            ..Default::default()
+        };
+        (registers, memory, IoBus::default())
+    }
+
+    #[test]
+    fn interrupt_requires_if() {
+        let (mut registers, mut memory, mut io) = machine();
+        io.raise_irq(0);
+        assert!(!accept_interrupt(&mut registers, &mut memory, &mut io));
+
+        registers.flags |= RegisterFile::IF_MASK;
+        assert!(accept_interrupt(&mut registers, &mut memory, &mut io));
+        assert_eq!((registers.cs, registers.ip), (0x1234, 0x0010));
+        assert_eq!(registers.flags & RegisterFile::IF_MASK, 0);
+        assert_eq!(registers.sp, 0xfa);
+        assert_eq!(&memory[0xfa..0x100], &[0x07, 0x00, 0x50, 0x00, 0x00, 0x02]);
+        assert_eq!(io.pic.isr, 1);
+    }
+
+    #[test]
+    fn sti_shadow() {
+        let (mut registers, mut memory, mut io) = machine();
+        io.raise_irq(0);
+
+        let sti = decode_instruction(&[0xfb, 0, 0, 0, 0, 0]);
+        simulate(&mut registers, &mut memory, &mut io, sti);
+        assert!(!accept_interrupt(&mut registers, &mut memory, &mut io));
+
+        let mov = decode_instruction(&[0x89, 0xd9, 0, 0, 0, 0]);
+        simulate(&mut registers, &mut memory, &mut io, mov);
+        assert!(accept_interrupt(&mut registers, &mut memory, &mut io));
+        // Return address points past both instructions.
+        assert_eq!(&memory[0xfa..0xfc], &[0x0a, 0x00]);
+    }
+
+    #[test]
+    fn eoi_through_port_io() {
+        let (mut registers, mut memory, mut io) = machine();
+        registers.flags |= RegisterFile::IF_MASK;
+        io.raise_irq(0);
+        io.lower_irq(0);
+        assert!(accept_interrupt(&mut registers, &mut memory, &mut io));
+
+        // mov al, 20h ; out 20h, al ; iret
+        for bytes in [[0xb0, 0x20], [0xe6, 0x20]] {
+            let padded = [bytes[0], bytes[1], 0, 0, 0, 0];
+            simulate(
+                &mut registers,
+                &mut memory,
+                &mut io,
+                decode_instruction(&padded),
+            );
+        }
+        assert_eq!(io.pic.isr, 0);
+
+        let iret = decode_instruction(&[0xcf, 0, 0, 0, 0, 0]);
+        simulate(&mut registers, &mut memory, &mut io, iret);
+        assert_eq!((registers.cs, registers.ip), (0x0050, 0x0007));
+        assert_eq!(registers.sp, 0x100);
+        assert_ne!(registers.flags & RegisterFile::IF_MASK, 0);
+    }
+
+    /// Steps a (possibly repeated) instruction until IP leaves it, returning
+    /// the number of steps and the total clock count.
+    fn run_string(registers: &mut RegisterFile, memory: &mut Memory, bytes: &[u8]) -> (u32, u32) {
+        let mut padded = [0; 6];
+        padded[..bytes.len()].copy_from_slice(bytes);
+        let instruction = decode_instruction(&padded);
+        let mut io = IoBus::default();
+        let (mut steps, mut cycles) = (0, 0);
+        let start = registers.ip;
+        loop {
+            steps += 1;
+            cycles += simulate(registers, memory, &mut io, instruction);
+            if registers.ip != start {
+                return (steps, cycles);
+            }
         }
-        MemoryOperandKind::Disp16_BP_DI(disp) => {
-            ((register_file.bp + register_file.di) as i16 + disp) as u16
-        }
-        MemoryOperandKind::Disp16_SI(disp) => (register_file.si as i16 + disp) as u16,
-        MemoryOperandKind::Disp16_DI(disp) => (register_file.di as i16 + disp) as u16,
-        MemoryOperandKind::Disp16_BP(disp) => (register_file.bp as i16 + disp) as u16,
-        MemoryOperandKind::Disp16_BX(disp) => (register_file.bx as i16 + disp) as u16,
-    } as usize;
+    }
+
+    #[test]
+    fn rep_movsb_forward() {
+        let mut memory = Memory::default();
+        memory[0x1000..0x1005].copy_from_slice(b"hello");
+        let mut registers = RegisterFile {
+            si: 0x1000,
+            di: 0x0010,
+            es: 0x0100,
+            cx: 5,
+            ..Default::default()
+        };
+
+        let (steps, cycles) = run_string(&mut registers, &mut memory, &[0xf3, 0xa4]);
+        assert_eq!(&memory[0x1010..0x1015], b"hello");
+        assert_eq!(
+            (registers.si, registers.di, registers.cx),
+            (0x1005, 0x0015, 0)
+        );
+        assert_eq!(registers.ip, 2);
+        assert_eq!(steps, 5);
+        assert_eq!(cycles, 9 + 5 * 17);
+
+        // CX = 0 does nothing.
+        registers.ip = 0;
+        let (steps, cycles) = run_string(&mut registers, &mut memory, &[0xf3, 0xa4]);
+        assert_eq!((steps, cycles), (1, 9));
+        assert_eq!(registers.si, 0x1005);
+    }
+
+    #[test]
+    fn stosw_backward() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ax: 0xbeef,
+            di: 0x0104,
+            cx: 3,
+            flags: RegisterFile::DF_MASK,
+            ..Default::default()
+        };
+
+        run_string(&mut registers, &mut memory, &[0xf3, 0xab]);
+        assert_eq!(&memory[0x100..0x106], &[0xef, 0xbe, 0xef, 0xbe, 0xef, 0xbe]);
+        assert_eq!(registers.di, 0x00fe);
+    }
+
+    #[test]
+    fn repe_cmpsb_stops_on_mismatch() {
+        let mut memory = Memory::default();
+        memory[0x100..0x106].copy_from_slice(b"abcdef");
+        memory[0x200..0x206].copy_from_slice(b"abcxef");
+        let mut registers = RegisterFile {
+            si: 0x100,
+            di: 0x200,
+            cx: 6,
+            ..Default::default()
+        };
+
+        let (steps, _) = run_string(&mut registers, &mut memory, &[0xf3, 0xa6]);
+        assert_eq!(steps, 4);
+        assert_eq!(
+            (registers.si, registers.di, registers.cx),
+            (0x104, 0x204, 2)
+        );
+        assert_eq!(registers.flags & RegisterFile::ZF_MASK, 0);
+        // 'd' - 'x' borrows.
+        assert_ne!(registers.flags & RegisterFile::CF_MASK, 0);
+    }
+
+    #[test]
+    fn repne_scasb_finds_byte() {
+        let mut memory = Memory::default();
+        memory[0x300..0x308].copy_from_slice(b"path/to\0");
+        let mut registers = RegisterFile {
+            ax: b'/' as u16,
+            di: 0x300,
+            cx: 0xffff,
+            ..Default::default()
+        };
+
+        let (steps, cycles) = run_string(&mut registers, &mut memory, &[0xf2, 0xae]);
+        assert_eq!(steps, 5);
+        assert_eq!(cycles, 9 + 5 * 15);
+        assert_eq!(registers.di, 0x305);
+        assert_eq!(registers.cx, 0xffff - 5);
+        assert_ne!(registers.flags & RegisterFile::ZF_MASK, 0);
+    }
+
+    #[test]
+    fn lodsw_without_prefix() {
+        let mut memory = Memory::default();
+        memory[0x10..0x12].copy_from_slice(&[0x34, 0x12]);
+        let mut registers = RegisterFile {
+            si: 0x10,
+            cx: 7,
+            ..Default::default()
+        };
+
+        let (steps, cycles) = run_string(&mut registers, &mut memory, &[0xad]);
+        assert_eq!((steps, cycles), (1, 12));
+        assert_eq!(
+            (registers.ax, registers.si, registers.cx),
+            (0x1234, 0x12, 7)
+        );
+    }
+
+    #[test]
+    fn prefix_cycles() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile::default();
+
+        // es lodsw
+        let (steps, cycles) = run_string(&mut registers, &mut memory, &[0x26, 0xad]);
+        assert_eq!((steps, cycles), (1, 12 + 2));
+
+        // xchg ax, cx: 91h, then with a segment prefix, then as 87h /r.
+        assert_eq!(step(&mut registers, &mut memory, &[0x91]), 3);
+        assert_eq!(step(&mut registers, &mut memory, &[0x2e, 0x91]), 3 + 2);
+        assert_eq!(step(&mut registers, &mut memory, &[0x87, 0xc8]), 4);
+    }
+
+    fn step(registers: &mut RegisterFile, memory: &mut Memory, bytes: &[u8]) -> u32 {
+        let mut padded = [0; 16];
+        padded[..bytes.len()].copy_from_slice(bytes);
+        let instruction = decode_instruction(&padded);
+        simulate(registers, memory, &mut IoBus::default(), instruction)
+    }
+
+    #[test]
+    fn multiply() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ax: 0x0080,
+            bx: 0x0002,
+            ..Default::default()
+        };
+        // mul bl
+        step(&mut registers, &mut memory, &[0xf6, 0xe3]);
+        assert_eq!(registers.ax, 0x0100);
+        assert_ne!(registers.flags & RegisterFile::CF_MASK, 0);
+        assert_ne!(registers.flags & RegisterFile::OF_MASK, 0);
+
+        // imul bl: -128 * 2 = -256
+        registers.ax = 0x0080;
+        step(&mut registers, &mut memory, &[0xf6, 0xeb]);
+        assert_eq!(registers.ax, 0xff00);
+        assert_ne!(registers.flags & RegisterFile::CF_MASK, 0);
+
+        // imul word [0x10]: -2 * 3 fits in AX, DX is the sign extension
+        memory[0x10..0x12].copy_from_slice(&[0x03, 0x00]);
+        registers.ax = 0xfffe;
+        step(&mut registers, &mut memory, &[0xf7, 0x2e, 0x10, 0x00]);
+        assert_eq!((registers.dx, registers.ax), (0xffff, 0xfffa));
+        assert_eq!(registers.flags & RegisterFile::CF_MASK, 0);
+        assert_eq!(registers.flags & RegisterFile::OF_MASK, 0);
+
+        // mul cx
+        registers.ax = 0x1234;
+        registers.cx = 0x0100;
+        step(&mut registers, &mut memory, &[0xf7, 0xe1]);
+        assert_eq!((registers.dx, registers.ax), (0x0012, 0x3400));
+    }
+
+    #[test]
+    fn divide() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ax: 1000,
+            bx: 7,
+            ..Default::default()
+        };
+        // div bl
+        step(&mut registers, &mut memory, &[0xf6, 0xf3]);
+        assert_eq!(registers.ax, 6 << 8 | 142);
+
+        // idiv bx: -100003 / 7
+        let dividend = -100003i32 as u32;
+        registers.dx = (dividend >> 16) as u16;
+        registers.ax = dividend as u16;
+        step(&mut registers, &mut memory, &[0xf7, 0xfb]);
+        assert_eq!(registers.ax as i16, -14286);
+        assert_eq!(registers.dx as i16, -1);
+    }
+
+    #[test]
+    fn divide_error_raises_int0() {
+        let mut memory = Memory::default();
+        // INT 0 -> 2000:0040
+        memory[0..4].copy_from_slice(&[0x40, 0x00, 0x00, 0x20]);
+        let mut registers = RegisterFile {
+            ax: 0x1234,
+            dx: 0x5678,
+            sp: 0x100,
+            ..Default::default()
+        };
+
+        // div cx with CX = 0
+        step(&mut registers, &mut memory, &[0xf7, 0xf1]);
+        assert_eq!((registers.cs, registers.ip), (0x2000, 0x0040));
+        assert_eq!((registers.dx, registers.ax), (0x5678, 0x1234));
+        // The 8086 pushes the address of the next instruction.
+        assert_eq!(&memory[0xfa..0xfc], &[0x02, 0x00]);
+
+        // div bl: 0x1234 / 0x10 does not fit in AL
+        registers.ip = 0;
+        registers.cs = 0;
+        registers.bx = 0x10;
+        step(&mut registers, &mut memory, &[0xf6, 0xf3]);
+        assert_eq!((registers.cs, registers.ip), (0x2000, 0x0040));
+
+        // idiv bl: -128 is out of range on the 8086
+        registers.ax = -256i16 as u16;
+        registers.bx = 2;
+        step(&mut registers, &mut memory, &[0xf6, 0xfb]);
+        assert_eq!(registers.ax, -256i16 as u16);
+        assert_eq!((registers.cs, registers.ip), (0x2000, 0x0040));
+    }
+
+    #[test]
+    fn unknown_opcode_raises_int6() {
+        let mut memory = Memory::default();
+        // INT 6 -> 3000:0080
+        memory[0x18..0x1c].copy_from_slice(&[0x80, 0x00, 0x00, 0x30]);
+        let mut registers = RegisterFile {
+            sp: 0x100,
+            ..Default::default()
+        };
+
+        let cycles = step(&mut registers, &mut memory, &[0x0f, 0x01]);
+        assert_eq!(cycles, 51);
+        assert_eq!((registers.cs, registers.ip), (0x3000, 0x0080));
+        assert_eq!(&memory[0xfa..0xfc], &[0x01, 0x00]);
+    }
+
+    fn flag(registers: &RegisterFile, mask: u16) -> bool {
+        registers.flags & mask != 0
+    }
+
+    #[test]
+    fn logical_ops() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ax: 0x00f0,
+            bx: 0x0f0f,
+            flags: RegisterFile::CF_MASK | RegisterFile::OF_MASK,
+            ..Default::default()
+        };
+
+        // and ax, bx
+        step(&mut registers, &mut memory, &[0x21, 0xd8]);
+        assert_eq!(registers.ax, 0x0000);
+        assert!(flag(&registers, RegisterFile::ZF_MASK));
+        assert!(flag(&registers, RegisterFile::PF_MASK));
+        assert!(!flag(&registers, RegisterFile::CF_MASK));
+        assert!(!flag(&registers, RegisterFile::OF_MASK));
 
-    addr
+        // or al, 81h
+        step(&mut registers, &mut memory, &[0x0c, 0x81]);
+        assert_eq!(registers.ax, 0x0081);
+        assert!(flag(&registers, RegisterFile::SF_MASK));
+        assert!(flag(&registers, RegisterFile::PF_MASK));
+
+        // xor bh, al
+        step(&mut registers, &mut memory, &[0x30, 0xc7]);
+        assert_eq!(registers.bx, 0x8e0f);
+        assert!(flag(&registers, RegisterFile::PF_MASK));
+
+        // test bx, 8000h leaves BX alone
+        step(&mut registers, &mut memory, &[0xf7, 0xc3, 0x00, 0x80]);
+        assert_eq!(registers.bx, 0x8e0f);
+        assert!(!flag(&registers, RegisterFile::ZF_MASK));
+        assert!(flag(&registers, RegisterFile::SF_MASK));
+
+        // not byte [10h]
+        memory[0x10] = 0x5a;
+        step(&mut registers, &mut memory, &[0xf6, 0x16, 0x10, 0x00]);
+        assert_eq!(memory[0x10], 0xa5);
+    }
+
+    #[test]
+    fn neg_and_sign_extended_immediate() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            cx: 5,
+            ..Default::default()
+        };
+
+        // neg cx
+        step(&mut registers, &mut memory, &[0xf7, 0xd9]);
+        assert_eq!(registers.cx, 0xfffb);
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+        assert!(flag(&registers, RegisterFile::SF_MASK));
+
+        // add cx, byte -5 (83 /0)
+        step(&mut registers, &mut memory, &[0x83, 0xc1, 0xfb]);
+        assert_eq!(registers.cx, 0xfff6);
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+
+        // neg of 8000h overflows
+        registers.cx = 0x8000;
+        step(&mut registers, &mut memory, &[0xf7, 0xd9]);
+        assert_eq!(registers.cx, 0x8000);
+        assert!(flag(&registers, RegisterFile::OF_MASK));
+
+        // adc/sbb carry in
+        registers.ax = 0x00ff;
+        registers.flags = RegisterFile::CF_MASK;
+        step(&mut registers, &mut memory, &[0x14, 0x00]);
+        assert_eq!(registers.ax, 0x0000);
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+        assert!(flag(&registers, RegisterFile::AF_MASK));
+        step(&mut registers, &mut memory, &[0x1c, 0x00]);
+        assert_eq!(registers.ax, 0x00ff);
+    }
+
+    #[test]
+    fn shifts() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ax: 0x81,
+            ..Default::default()
+        };
+
+        // shl al, 1
+        step(&mut registers, &mut memory, &[0xd0, 0xe0]);
+        assert_eq!(registers.ax, 0x02);
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+        assert!(flag(&registers, RegisterFile::OF_MASK));
+
+        // sar ax, cl with CL = 4
+        registers.ax = 0x8010;
+        registers.cx = 4;
+        let cycles = step(&mut registers, &mut memory, &[0xd3, 0xf8]);
+        assert_eq!(registers.ax, 0xf801);
+        assert!(!flag(&registers, RegisterFile::CF_MASK));
+        assert!(flag(&registers, RegisterFile::SF_MASK));
+        assert_eq!(cycles, 8 + 4 * 4);
+
+        // shr ax, 1 sets OF from the original sign bit
+        step(&mut registers, &mut memory, &[0xd1, 0xe8]);
+        assert_eq!(registers.ax, 0x7c00);
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+        assert!(flag(&registers, RegisterFile::OF_MASK));
+
+        // shift by CL = 0 changes nothing
+        registers.cx = 0;
+        let flags = registers.flags;
+        step(&mut registers, &mut memory, &[0xd3, 0xe0]);
+        assert_eq!((registers.ax, registers.flags), (0x7c00, flags));
+    }
+
+    #[test]
+    fn rotates() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            bx: 0x8001,
+            ..Default::default()
+        };
+
+        // rol bx, 1
+        step(&mut registers, &mut memory, &[0xd1, 0xc3]);
+        assert_eq!(registers.bx, 0x0003);
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+        assert!(flag(&registers, RegisterFile::OF_MASK));
+
+        // ror bl, 1
+        step(&mut registers, &mut memory, &[0xd0, 0xcb]);
+        assert_eq!(registers.bx, 0x0081);
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+
+        // rcl bl, 1 rotates the carry in
+        step(&mut registers, &mut memory, &[0xd0, 0xd3]);
+        assert_eq!(registers.bx, 0x0003);
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+
+        // rcr bx, cl with CL = 2
+        registers.cx = 2;
+        step(&mut registers, &mut memory, &[0xd3, 0xdb]);
+        assert_eq!(registers.bx, 0xc000);
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+    }
+
+    #[test]
+    fn decimal_adjust_known_results() {
+        use RegisterFile as R;
+        const DEFINED: u16 = R::CF_MASK | R::AF_MASK;
+
+        // (instruction, AX in, flags in, AX out, CF/AF out)
+        let cases: &[(&[u8], u16, u16, u16, u16)] = &[
+            // 79h + 35h = AEh
+            (&[0x27], 0x00ae, 0, 0x0014, R::CF_MASK | R::AF_MASK),
+            (&[0x27], 0x009a, 0, 0x0000, R::CF_MASK | R::AF_MASK),
+            // 38h + 29h = 61h, AF
+            (&[0x27], 0x0061, R::AF_MASK, 0x0067, R::AF_MASK),
+            (&[0x27], 0x0045, 0, 0x0045, 0),
+            // 8086 compares against 9Fh when AF is set on entry
+            (&[0x27], 0x009c, R::AF_MASK, 0x00a2, R::AF_MASK),
+            // 35h - 47h = EEh, AF and CF
+            (
+                &[0x2f],
+                0x00ee,
+                R::CF_MASK | R::AF_MASK,
+                0x0088,
+                R::CF_MASK | R::AF_MASK,
+            ),
+            (&[0x2f], 0x0012, 0, 0x0012, 0),
+            // '8' + '9' = 71h, AF
+            (&[0x37], 0x0071, R::AF_MASK, 0x0107, R::CF_MASK | R::AF_MASK),
+            (&[0x37], 0x0005, 0, 0x0005, 0),
+            // AL + 6 does not carry into AH on the 8086
+            (&[0x37], 0x00fa, 0, 0x0100, R::CF_MASK | R::AF_MASK),
+            // '2' - '8' = FAh, AF and CF
+            (
+                &[0x3f],
+                0x00fa,
+                R::CF_MASK | R::AF_MASK,
+                0xff04,
+                R::CF_MASK | R::AF_MASK,
+            ),
+            (&[0xd4, 0x0a], 0x003f, 0, 0x0603, 0),
+            (&[0xd4, 0x10], 0x003f, 0, 0x030f, 0),
+            (&[0xd5, 0x0a], 0x0603, 0, 0x003f, 0),
+            (&[0xd5, 0x10], 0x0f0f, 0, 0x00ff, 0),
+        ];
+
+        let mut memory = Memory::default();
+        for &(bytes, ax, flags, expected_ax, expected_flags) in cases {
+            let mut registers = RegisterFile {
+                ax,
+                flags,
+                ..Default::default()
+            };
+            step(&mut registers, &mut memory, bytes);
+            assert_eq!(registers.ax, expected_ax, "{bytes:02x?} with AX={ax:04x}");
+            assert_eq!(
+                registers.flags & DEFINED,
+                expected_flags,
+                "{bytes:02x?} with AX={ax:04x}"
+            );
+        }
+    }
+
+    #[test]
+    fn decimal_adjust_result_flags() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ax: 0x009a,
+            ..Default::default()
+        };
+        // daa: 9Ah -> 00h
+        step(&mut registers, &mut memory, &[0x27]);
+        assert!(flag(&registers, RegisterFile::ZF_MASK));
+        assert!(flag(&registers, RegisterFile::PF_MASK));
+        assert!(!flag(&registers, RegisterFile::SF_MASK));
+
+        // daa: 7Ah -> 80h sets SF and the undocumented OF
+        registers.ax = 0x007a;
+        registers.flags = 0;
+        step(&mut registers, &mut memory, &[0x27]);
+        assert_eq!(registers.ax, 0x0080);
+        assert!(flag(&registers, RegisterFile::SF_MASK));
+        assert!(flag(&registers, RegisterFile::OF_MASK));
+
+        // aam: SZP follow AL
+        registers.ax = 0x0050;
+        step(&mut registers, &mut memory, &[0xd4, 0x0a]);
+        assert_eq!(registers.ax, 0x0800);
+        assert!(flag(&registers, RegisterFile::ZF_MASK));
+    }
+
+    #[test]
+    fn aam_zero_raises_int0() {
+        let mut memory = Memory::default();
+        memory[0..4].copy_from_slice(&[0x40, 0x00, 0x00, 0x20]);
+        let mut registers = RegisterFile {
+            ax: 0x0042,
+            sp: 0x100,
+            ..Default::default()
+        };
+
+        step(&mut registers, &mut memory, &[0xd4, 0x00]);
+        assert_eq!(registers.ax, 0x0042);
+        assert_eq!((registers.cs, registers.ip), (0x2000, 0x0040));
+    }
+
+    #[test]
+    fn inc_dec_preserve_carry() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            cx: 0xffff,
+            flags: RegisterFile::CF_MASK,
+            ..Default::default()
+        };
+
+        // inc cx wraps to zero without touching CF
+        step(&mut registers, &mut memory, &[0x41]);
+        assert_eq!(registers.cx, 0);
+        assert!(flag(&registers, RegisterFile::ZF_MASK));
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+
+        // dec byte [20h]: 80h -> 7Fh overflows
+        memory[0x20] = 0x80;
+        registers.flags = 0;
+        step(&mut registers, &mut memory, &[0xfe, 0x0e, 0x20, 0x00]);
+        assert_eq!(memory[0x20], 0x7f);
+        assert!(flag(&registers, RegisterFile::OF_MASK));
+        assert!(!flag(&registers, RegisterFile::CF_MASK));
+    }
+
+    #[test]
+    fn exchange_and_load_address() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ax: 0x1111,
+            bx: 0x0100,
+            si: 0x0020,
+            ..Default::default()
+        };
+
+        // xchg ax, bx
+        step(&mut registers, &mut memory, &[0x93]);
+        assert_eq!((registers.ax, registers.bx), (0x0100, 0x1111));
+
+        // xchg word [bx + si], ax
+        registers.bx = 0x0100;
+        memory[0x120..0x122].copy_from_slice(&[0xcd, 0xab]);
+        step(&mut registers, &mut memory, &[0x87, 0x00]);
+        assert_eq!(registers.ax, 0xabcd);
+        assert_eq!(&memory[0x120..0x122], &[0x00, 0x01]);
+
+        // lea dx, [bx + si - 1] uses the offset, not the segment
+        registers.ds = 0x1000;
+        step(&mut registers, &mut memory, &[0x8d, 0x50, 0xff]);
+        assert_eq!(registers.dx, 0x011f);
+
+        // lds si, [bx]
+        memory[0x10100..0x10104].copy_from_slice(&[0x34, 0x12, 0x00, 0x20]);
+        step(&mut registers, &mut memory, &[0xc5, 0x37]);
+        assert_eq!((registers.ds, registers.si), (0x2000, 0x1234));
+
+        // les di, [si]
+        memory[0x21234..0x21238].copy_from_slice(&[0x78, 0x56, 0x00, 0x30]);
+        step(&mut registers, &mut memory, &[0xc4, 0x3c]);
+        assert_eq!((registers.es, registers.di), (0x3000, 0x5678));
+    }
+
+    #[test]
+    fn conversions_and_flag_transfer() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ax: 0x1280,
+            ..Default::default()
+        };
+
+        step(&mut registers, &mut memory, &[0x98]);
+        assert_eq!(registers.ax, 0xff80);
+        step(&mut registers, &mut memory, &[0x99]);
+        assert_eq!(registers.dx, 0xffff);
+        registers.ax = 0x7fff;
+        step(&mut registers, &mut memory, &[0x99]);
+        assert_eq!(registers.dx, 0x0000);
+
+        // xlat
+        memory[0x205] = 0x42;
+        registers.ax = 0x0005;
+        registers.bx = 0x0200;
+        step(&mut registers, &mut memory, &[0xd7]);
+        assert_eq!(registers.ax, 0x0042);
+
+        // lahf / sahf
+        registers.flags = RegisterFile::SF_MASK | RegisterFile::CF_MASK | RegisterFile::OF_MASK;
+        step(&mut registers, &mut memory, &[0x9f]);
+        assert_eq!(registers.ax, 0x8342);
+        registers.ax = 0x4400;
+        step(&mut registers, &mut memory, &[0x9e]);
+        assert_eq!(
+            registers.flags,
+            RegisterFile::ZF_MASK | RegisterFile::PF_MASK | RegisterFile::OF_MASK
+        );
+    }
+
+    #[test]
+    fn conditional_jumps_and_loops() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ip: 0x0100,
+            flags: RegisterFile::SF_MASK,
+            ..Default::default()
+        };
+
+        // jl -4: SF != OF
+        assert_eq!(step(&mut registers, &mut memory, &[0x7c, 0xfc]), 16);
+        assert_eq!(registers.ip, 0x00fe);
+        // ja +2 is not taken with ZF clear and CF set
+        registers.flags = RegisterFile::CF_MASK;
+        assert_eq!(step(&mut registers, &mut memory, &[0x77, 0x02]), 4);
+        assert_eq!(registers.ip, 0x0100);
+
+        // loop +16 with cx = 2 is taken once
+        registers.cx = 2;
+        assert_eq!(step(&mut registers, &mut memory, &[0xe2, 0x10]), 17);
+        assert_eq!((registers.cx, registers.ip), (1, 0x0112));
+        assert_eq!(step(&mut registers, &mut memory, &[0xe2, 0x10]), 5);
+        assert_eq!((registers.cx, registers.ip), (0, 0x0114));
+
+        // loopnz stops on ZF even with cx left
+        registers.cx = 5;
+        registers.flags = RegisterFile::ZF_MASK;
+        step(&mut registers, &mut memory, &[0xe0, 0x10]);
+        assert_eq!((registers.cx, registers.ip), (4, 0x0116));
+
+        // jcxz
+        registers.cx = 0;
+        step(&mut registers, &mut memory, &[0xe3, 0x02]);
+        assert_eq!(registers.ip, 0x011a);
+    }
+
+    #[test]
+    fn jumps() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            ip: 0xfff0,
+            ..Default::default()
+        };
+
+        // jmp near wraps within the segment
+        step(&mut registers, &mut memory, &[0xe9, 0x20, 0x00]);
+        assert_eq!(registers.ip, 0x0013);
+
+        // jmp short
+        step(&mut registers, &mut memory, &[0xeb, 0xfe]);
+        assert_eq!(registers.ip, 0x0013);
+
+        // jmp bx
+        registers.bx = 0x0400;
+        assert_eq!(step(&mut registers, &mut memory, &[0xff, 0xe3]), 11);
+        assert_eq!(registers.ip, 0x0400);
+
+        // jmp word [bx]
+        memory[0x400..0x402].copy_from_slice(&[0x34, 0x12]);
+        step(&mut registers, &mut memory, &[0xff, 0x27]);
+        assert_eq!(registers.ip, 0x1234);
+
+        // jmp 2000:0010
+        step(&mut registers, &mut memory, &[0xea, 0x10, 0x00, 0x00, 0x20]);
+        assert_eq!((registers.cs, registers.ip), (0x2000, 0x0010));
+
+        // jmp far [bx]
+        memory[0x400..0x404].copy_from_slice(&[0x00, 0x01, 0x00, 0xf0]);
+        assert_eq!(step(&mut registers, &mut memory, &[0xff, 0x2f]), 29);
+        assert_eq!((registers.cs, registers.ip), (0xf000, 0x0100));
+    }
+
+    #[test]
+    fn calls_and_returns() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            cs: 0x1000,
+            ip: 0x0100,
+            sp: 0x0200,
+            ..Default::default()
+        };
+
+        // call +0x10
+        step(&mut registers, &mut memory, &[0xe8, 0x10, 0x00]);
+        assert_eq!((registers.ip, registers.sp), (0x0113, 0x01fe));
+        assert_eq!(&memory[0x1fe..0x200], &[0x03, 0x01]);
+
+        // call 3000:0040
+        step(&mut registers, &mut memory, &[0x9a, 0x40, 0x00, 0x00, 0x30]);
+        assert_eq!((registers.cs, registers.ip), (0x3000, 0x0040));
+        assert_eq!(registers.sp, 0x01fa);
+        assert_eq!(&memory[0x1fa..0x1fe], &[0x18, 0x01, 0x00, 0x10]);
+
+        // retf
+        step(&mut registers, &mut memory, &[0xcb]);
+        assert_eq!(
+            (registers.cs, registers.ip, registers.sp),
+            (0x1000, 0x0118, 0x01fe)
+        );
+
+        // ret 4 discards the arguments above the return address
+        step(&mut registers, &mut memory, &[0xc2, 0x04, 0x00]);
+        assert_eq!((registers.ip, registers.sp), (0x0103, 0x0204));
+
+        // call far [si]
+        registers.si = 0x0300;
+        memory[0x300..0x304].copy_from_slice(&[0x00, 0x05, 0x00, 0x40]);
+        step(&mut registers, &mut memory, &[0xff, 0x1c]);
+        assert_eq!((registers.cs, registers.ip), (0x4000, 0x0500));
+        assert_eq!(&memory[0x200..0x204], &[0x05, 0x01, 0x00, 0x10]);
+
+        // call ax
+        registers.ax = 0x0600;
+        step(&mut registers, &mut memory, &[0xff, 0xd0]);
+        assert_eq!((registers.ip, registers.sp), (0x0600, 0x01fe));
+    }
+
+    #[test]
+    fn flag_control() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile::default();
+
+        step(&mut registers, &mut memory, &[0xf9]);
+        assert!(flag(&registers, RegisterFile::CF_MASK));
+        step(&mut registers, &mut memory, &[0xf5]);
+        assert!(!flag(&registers, RegisterFile::CF_MASK));
+        step(&mut registers, &mut memory, &[0xf5]);
+        step(&mut registers, &mut memory, &[0xf8]);
+        assert!(!flag(&registers, RegisterFile::CF_MASK));
+
+        step(&mut registers, &mut memory, &[0xfd]);
+        assert_eq!(registers.flags, RegisterFile::DF_MASK);
+        step(&mut registers, &mut memory, &[0xfc]);
+        assert_eq!(registers.flags, 0);
+
+        // nop, wait and a locked instruction only advance IP
+        assert_eq!(step(&mut registers, &mut memory, &[0x90]), 3);
+        assert_eq!(step(&mut registers, &mut memory, &[0x9b]), 3);
+        step(&mut registers, &mut memory, &[0xf0, 0x87, 0xd8]);
+        assert_eq!(registers.ip, 11);
+    }
+
+    #[test]
+    fn segment_override() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            bx: 0x0010,
+            bp: 0x0020,
+            ds: 0x1000,
+            es: 0x2000,
+            ss: 0x3000,
+            ..Default::default()
+        };
+        memory[0x10010..0x10012].copy_from_slice(&[0x11, 0x11]);
+        memory[0x20010..0x20012].copy_from_slice(&[0x22, 0x22]);
+        memory[0x10020..0x10022].copy_from_slice(&[0x33, 0x33]);
+
+        // mov ax, es:[bx]
+        assert_eq!(
+            step(&mut registers, &mut memory, &[0x26, 0x8b, 0x07]),
+            2 + 8 + 5
+        );
+        assert_eq!(registers.ax, 0x2222);
+        // mov ax, ds:[bp] instead of the default SS
+        step(&mut registers, &mut memory, &[0x3e, 0x8b, 0x46, 0x00]);
+        assert_eq!(registers.ax, 0x3333);
+        // mov ss:[bx], ax
+        step(&mut registers, &mut memory, &[0x36, 0x89, 0x07]);
+        assert_eq!(&memory[0x30010..0x30012], &[0x33, 0x33]);
+
+        // es lodsw reads ES:SI
+        registers.si = 0x0010;
+        step(&mut registers, &mut memory, &[0x26, 0xad]);
+        assert_eq!((registers.ax, registers.si), (0x2222, 0x0012));
+
+        // cs xlat
+        registers.cs = 0x4000;
+        registers.ax = 0x0001;
+        memory[0x40011] = 0x99;
+        step(&mut registers, &mut memory, &[0x2e, 0xd7]);
+        assert_eq!(registers.ax, 0x0099);
+    }
+
+    #[test]
+    fn address_wraparound() {
+        let mut memory = Memory::default();
+        let mut registers = RegisterFile {
+            bx: 0xfff0,
+            si: 0x0020,
+            ds: 0x1000,
+            ..Default::default()
+        };
+        memory[0x10010..0x10012].copy_from_slice(&[0x34, 0x12]);
+
+        // mov ax, [bx+si]: FFF0h + 20h wraps to offset 10h.
+        step(&mut registers, &mut memory, &[0x8b, 0x00]);
+        assert_eq!(registers.ax, 0x1234);
+        // mov ax, [bx+si-30h] with a negative displacement past zero.
+        registers.si = 0x0000;
+        registers.bx = 0x0040;
+        step(&mut registers, &mut memory, &[0x8b, 0x40, 0xd0]);
+        assert_eq!(registers.ax, 0x1234);
+
+        // A word at offset FFFFh takes its high byte from offset 0.
+        registers.bx = 0xffff;
+        registers.ax = 0xabcd;
+        step(&mut registers, &mut memory, &[0x89, 0x07]);
+        assert_eq!((memory[0x1ffff], memory[0x10000]), (0xcd, 0xab));
+        assert_eq!(memory[0x20000], 0);
+        step(&mut registers, &mut memory, &[0x8b, 0x0f]);
+        assert_eq!(registers.cx, 0xabcd);
+
+        // FFFF:0010 wraps past 1 MiB to physical 0.
+        registers.ds = 0xffff;
+        registers.bx = 0x0010;
+        step(&mut registers, &mut memory, &[0x89, 0x07]);
+        assert_eq!(&memory[0x00000..0x00002], &[0xcd, 0xab]);
+
+        // A push with SP at 1 splits across the end of the stack segment.
+        registers.ss = 0x3000;
+        registers.sp = 0x0001;
+        registers.ip = 0xfffe;
+        step(&mut registers, &mut memory, &[0xe8, 0x00, 0x00]);
+        assert_eq!(registers.sp, 0xffff);
+        assert_eq!((memory[0x3ffff], memory[0x30000]), (0x01, 0x00));
+        assert_eq!(registers.ip, 0x0001);
+    }
 }
@@ -0,0 +1,286 @@
+use std::fmt::{self, Display};
+use std::io::{self, Read, Write};
+
+use crate::*;
+
+/// Complete machine state: CPU registers, memory and every device on the
+/// I/O bus, including the elapsed cycle count and any attached disk.
+///
+/// The file format is a magic number and format version followed by
+/// little-endian fields. Restoring refuses versions it does not know.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub registers: RegisterFile,
+    pub memory: Vec<u8>,
+    pub io: IoBus,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// Not a snapshot file.
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// A field holds a value no snapshot writer produces.
+    Corrupt,
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::BadMagic => write!(f, "not a machine snapshot"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {version}")
+            }
+            Self::Corrupt => write!(f, "snapshot is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl Snapshot {
+    pub const MAGIC: [u8; 8] = *b"r8086snp";
+    pub const VERSION: u16 = 1;
+
+    pub fn capture(registers: &RegisterFile, memory: &[u8], io: &IoBus) -> Self {
+        Self {
+            registers: *registers,
+            memory: memory.to_vec(),
+            io: io.clone(),
+        }
+    }
+
+    pub fn save(&self, output: &mut impl Write) -> io::Result<()> {
+        let mut w = SnapshotWriter::default();
+        w.bytes(&Self::MAGIC);
+        w.u16(Self::VERSION);
+
+        let r = &self.registers;
+        for value in [
+            r.ax, r.bx, r.cx, r.dx, r.sp, r.bp, r.si, r.di, r.cs, r.ds, r.es, r.ss, r.ip, r.flags,
+        ] {
+            w.u16(value);
+        }
+        w.bool(r.interrupt_shadow);
+
+        w.u32(self.memory.len() as u32);
+        w.bytes(&self.memory);
+        self.io.save(&mut w);
+
+        output.write_all(&w.0)
+    }
+
+    pub fn load(input: &mut impl Read) -> Result<Self, SnapshotError> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        let mut r = SnapshotReader { data: &data };
+
+        if r.bytes(Self::MAGIC.len()).ok() != Some(&Self::MAGIC[..]) {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version != Self::VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let registers = RegisterFile {
+            ax: r.u16()?,
+            bx: r.u16()?,
+            cx: r.u16()?,
+            dx: r.u16()?,
+            sp: r.u16()?,
+            bp: r.u16()?,
+            si: r.u16()?,
+            di: r.u16()?,
+            cs: r.u16()?,
+            ds: r.u16()?,
+            es: r.u16()?,
+            ss: r.u16()?,
+            ip: r.u16()?,
+            flags: r.u16()?,
+            interrupt_shadow: r.bool()?,
+        };
+
+        let len = r.u32()? as usize;
+        let memory = r.bytes(len)?.to_vec();
+        let io = IoBus::restore(&mut r)?;
+
+        if !r.data.is_empty() {
+            return Err(SnapshotError::Corrupt);
+        }
+        Ok(Self {
+            registers,
+            memory,
+            io,
+        })
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SnapshotWriter(Vec<u8>);
+
+impl SnapshotWriter {
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.0.push(value as u8);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) {
+        self.0.extend_from_slice(value);
+    }
+}
+
+pub(crate) struct SnapshotReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.data.len() < len {
+            return Err(SnapshotError::Corrupt);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Corrupt),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let bytes = self.bytes(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A machine part way through a run: timer programmed and counting,
+    /// an interrupt in service and a disk attached.
+    fn running_machine() -> Snapshot {
+        let mut memory = vec![0; 1024 * 1024];
+        memory[0x1234] = 0x56;
+        let registers = RegisterFile {
+            ax: 0x1111,
+            cs: 0xf000,
+            ip: 0xfff0,
+            sp: 0xfffe,
+            flags: 0x0246,
+            interrupt_shadow: true,
+            ..Default::default()
+        };
+
+        let mut io = IoBus::default();
+        io.write8(Pit::CONTROL_PORT, 0b0011_0110);
+        io.write8(Pit::COUNTER0_PORT, 0x34);
+        io.write8(Pit::COUNTER0_PORT, 0x12);
+        io.write8(Pit::CONTROL_PORT, 0b0100_0000);
+        io.tick(1001);
+        io.raise_irq(3);
+        io.pic.acknowledge();
+        io.disk = Some(Disk::new(vec![0xaa; 1024], 0));
+
+        Snapshot::capture(&registers, &memory, &io)
+    }
+
+    #[test]
+    fn round_trip() {
+        let snapshot = running_machine();
+        let mut file = Vec::new();
+        snapshot.save(&mut file).unwrap();
+
+        let restored = Snapshot::load(&mut &file[..]).unwrap();
+        assert_eq!(restored, snapshot);
+        assert_eq!(restored.io.cycles, 1001);
+
+        // The restored machine carries on exactly like the original.
+        let (mut a, mut b) = (snapshot.io, restored.io);
+        for _ in 0..5000 {
+            a.tick(3);
+            b.tick(3);
+        }
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn rejects_foreign_files() {
+        let mut file = Vec::new();
+        running_machine().save(&mut file).unwrap();
+
+        let mut other_version = file.clone();
+        other_version[8] = 2;
+        assert!(matches!(
+            Snapshot::load(&mut &other_version[..]),
+            Err(SnapshotError::UnsupportedVersion(2))
+        ));
+
+        assert!(matches!(
+            Snapshot::load(&mut &b"not a snapshot"[..]),
+            Err(SnapshotError::BadMagic)
+        ));
+
+        file.truncate(file.len() - 1);
+        assert!(matches!(
+            Snapshot::load(&mut &file[..]),
+            Err(SnapshotError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn rejects_zero_disk_geometry() {
+        let mut file = Vec::new();
+        running_machine().save(&mut file).unwrap();
+
+        // The disk comes last: geometry, status, image length and the image.
+        let sectors_per_track = file.len() - 1024 - 4 - 2;
+        file[sectors_per_track] = 0;
+        assert!(matches!(
+            Snapshot::load(&mut &file[..]),
+            Err(SnapshotError::Corrupt)
+        ));
+    }
+}
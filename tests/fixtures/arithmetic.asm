@@ -0,0 +1,18 @@
+bits 16
+
+; 1234 / 10, then the remainder times 7.
+mov ax, 1234
+mov bx, 10
+xor dx, dx
+div bx
+mov cx, ax
+mov ax, dx
+mov dx, 7
+mul dx
+
+shl cx, 1
+sar cx, 1
+and cx, 0xff
+or ax, 0x100
+neg bx
+not dx
@@ -0,0 +1,29 @@
+bits 16
+
+mov word [0x100], 0x0201
+mov word [0x102], 0x0403
+
+; Copy the four bytes to 0x200.
+mov si, 0x100
+mov di, 0x200
+mov cx, 4
+cld
+rep movsb
+
+call double
+
+; Add the doubled word to dx three times.
+mov cx, 3
+again:
+	add dx, ax
+	loop again
+
+mov [0x210], dx
+jmp done
+
+double:
+	mov ax, [0x200]
+	add ax, ax
+	ret
+
+done:
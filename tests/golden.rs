@@ -0,0 +1,139 @@
+//! Golden-file tests over the programs in `tests/fixtures` and over
+//! `input/program.asm`.
+//!
+//! Each `<name>.asm` comes with a prebuilt `<name>.bin`. The binary is
+//! disassembled and compared against `tests/fixtures/<name>.disasm`, then run
+//! the way the `r8086` binary runs `input/program.bin` and its registers
+//! compared against `tests/fixtures/<name>.registers`. When `nasm` is on the
+//! path the source must assemble to the prebuilt binary, and so must the
+//! disassembly; without it those checks are skipped with a note on stderr.
+//!
+//! Run with `UPDATE_GOLDEN=1` to rewrite the golden files instead.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use r8086::*;
+
+/// Instructions a fixture may run before it is considered stuck.
+const STEP_LIMIT: usize = 1_000_000;
+
+#[test]
+fn fixtures() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let dir = root.join("tests/fixtures");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let nasm = Command::new("nasm").arg("-v").output().is_ok();
+    if !nasm {
+        eprintln!("nasm not found, skipping the re-assembly checks");
+    }
+
+    let mut sources: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "asm"))
+        .collect();
+    sources.sort();
+    assert!(!sources.is_empty(), "no fixtures in {}", dir.display());
+    sources.push(root.join("input/program.asm"));
+
+    let mut mismatches = Vec::new();
+    for source in sources {
+        let name = source.file_stem().unwrap().to_string_lossy().into_owned();
+        let binary = std::fs::read(source.with_extension("bin"))
+            .unwrap_or_else(|_| panic!("{name}.asm has no prebuilt {name}.bin"));
+
+        let disassembly = disassemble(&binary);
+        let registers = run(&binary);
+        let mut report = Vec::new();
+        registers.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+
+        for (extension, actual) in [("disasm", &disassembly), ("registers", &report)] {
+            let golden = dir.join(format!("{name}.{extension}"));
+            if update {
+                std::fs::write(&golden, actual).unwrap();
+                continue;
+            }
+            let expected = std::fs::read_to_string(&golden).unwrap_or_default();
+            if &expected != actual {
+                mismatches.push(format!(
+                    "{name}.{extension} differs\n--- expected\n{expected}--- actual\n{actual}"
+                ));
+            }
+        }
+
+        if !nasm {
+            continue;
+        }
+        let source_text = std::fs::read_to_string(&source).unwrap();
+        for (what, text) in [("source", &source_text), ("disassembly", &disassembly)] {
+            if assemble(text, &name) != binary {
+                mismatches.push(format!("{name}: {what} assembles to a different binary"));
+            }
+        }
+    }
+
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+fn disassemble(binary: &[u8]) -> String {
+    let mut text = String::from("bits 16\n");
+    let mut offset = 0;
+    while offset < binary.len() {
+        let mut window = [0; 16];
+        let end = (offset + window.len()).min(binary.len());
+        window[..end - offset].copy_from_slice(&binary[offset..end]);
+        let instruction = decode_instruction(&window);
+        offset += instruction.length as usize;
+        text += &format!("{instruction}\n");
+    }
+    text
+}
+
+/// Loads the binary at 0000:0000 and runs until IP leaves it.
+fn run(binary: &[u8]) -> RegisterFile {
+    let mut memory = Memory::default();
+    memory[..binary.len()].copy_from_slice(binary);
+    let mut registers = RegisterFile::default();
+    let mut io = IoBus::default();
+
+    for _ in 0..STEP_LIMIT {
+        if registers.ip as usize >= binary.len() {
+            return registers;
+        }
+        let start = registers.ip as usize;
+        let mut window = [0; 16];
+        window.copy_from_slice(&memory[start..start + 16]);
+        let cycles = simulate(
+            &mut registers,
+            &mut memory,
+            &mut io,
+            decode_instruction(&window),
+        );
+        io.tick(cycles);
+    }
+    panic!("program still running after {STEP_LIMIT} instructions");
+}
+
+fn assemble(source: &str, name: &str) -> Vec<u8> {
+    let dir = std::env::temp_dir().join(format!("r8086-golden-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let asm: PathBuf = dir.join(format!("{name}.asm"));
+    let bin = asm.with_extension("bin");
+    std::fs::write(&asm, source).unwrap();
+
+    let status = Command::new("nasm")
+        .arg("-f")
+        .arg("bin")
+        .arg("-o")
+        .arg(&bin)
+        .arg(&asm)
+        .status()
+        .expect("nasm failed to run");
+    let binary = std::fs::read(&bin);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(status.success(), "nasm rejected {name}");
+    binary.unwrap()
+}
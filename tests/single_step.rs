@@ -0,0 +1,221 @@
+//! Runs the 8088 SingleStepTests vectors: one JSON file per opcode (`00.json`,
+//! or `80.3.json` for a group opcode with its reg field), each a list of
+//! cases with the machine state before and after a single instruction.
+//!
+//! The full suite is not checked in. Point `SINGLE_STEP_TESTS` at a
+//! directory of uncompressed files, otherwise `tests/single_step` is used,
+//! and that run is skipped if neither exists. A `8088.json` metadata file in
+//! the same directory supplies the flags each opcode leaves undefined.
+//!
+//! `tests/single_step_sample` always runs: a few hand-written cases in the
+//! same format for 00h and D0h /4, with a trimmed metadata file, so the
+//! runner itself stays tested.
+
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use r8086::*;
+use serde_json::Value;
+
+#[derive(Default)]
+struct Tally {
+    passed: usize,
+    failed: usize,
+    /// Cases the decoder or simulator panicked on.
+    unsupported: usize,
+    first_failure: Option<String>,
+}
+
+#[test]
+fn sample_vectors() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/single_step_sample");
+    assert_eq!(run_vectors(&dir), 2);
+}
+
+#[test]
+fn single_step_vectors() {
+    let dir = std::env::var_os("SINGLE_STEP_TESTS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/single_step"));
+    if !dir.is_dir() {
+        eprintln!("no test vectors in {}, skipping", dir.display());
+        return;
+    }
+    run_vectors(&dir);
+}
+
+/// Runs every opcode file in `dir`, printing a line per opcode, and fails if
+/// any case does. Returns the number of opcodes run.
+fn run_vectors(dir: &Path) -> usize {
+    // The panic hook is process wide, so runs take turns.
+    static RUNNING: Mutex<()> = Mutex::new(());
+    let _running = RUNNING.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let entries = std::fs::read_dir(dir).expect("unreadable test directory");
+    let metadata = std::fs::read(dir.join("8088.json"))
+        .ok()
+        .map(|bytes| serde_json::from_slice::<Value>(&bytes).expect("bad metadata"));
+
+    let mut files: Vec<_> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "json")
+                && path.file_stem().is_some_and(|stem| stem != "8088")
+        })
+        .collect();
+    files.sort();
+
+    let mut results = BTreeMap::new();
+    for path in files {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let flags_mask = flags_mask(metadata.as_ref(), &name);
+        let cases: Vec<Value> =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).expect("bad test file");
+
+        let mut tally = Tally::default();
+        for case in &cases {
+            match run_quietly(|| run_case(case, flags_mask)) {
+                Ok(Ok(())) => tally.passed += 1,
+                Ok(Err(mismatch)) => {
+                    tally.failed += 1;
+                    tally.first_failure.get_or_insert_with(|| {
+                        format!("{}: {mismatch}", case["name"].as_str().unwrap_or("?"))
+                    });
+                }
+                Err(_) => tally.unsupported += 1,
+            }
+        }
+        results.insert(name, tally);
+    }
+
+    let mut failing = 0;
+    for (opcode, tally) in &results {
+        let total = tally.passed + tally.failed + tally.unsupported;
+        let status = if tally.passed == total { "ok" } else { "FAIL" };
+        print!("{opcode:>6} {status:>4} {:>6}/{total}", tally.passed);
+        if tally.unsupported > 0 {
+            print!(" ({} unsupported)", tally.unsupported);
+        }
+        println!();
+        if let Some(failure) = &tally.first_failure {
+            println!("         {failure}");
+        }
+        if tally.passed != total {
+            failing += 1;
+        }
+    }
+    assert_eq!(failing, 0, "{failing} of {} opcodes failing", results.len());
+    results.len()
+}
+
+/// Runs `f` with panic messages silenced: a case the simulator panics on is
+/// reported as unsupported, not printed.
+fn run_quietly<R>(f: impl FnOnce() -> R) -> std::thread::Result<R> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    panic::set_hook(hook);
+    result
+}
+
+/// Flags the metadata marks as defined for `name`, all of them by default.
+fn flags_mask(metadata: Option<&Value>, name: &str) -> u16 {
+    let Some(metadata) = metadata else {
+        return 0xffff;
+    };
+    let mut parts = name.split('.');
+    let opcode = &metadata["opcodes"][parts.next().unwrap().to_uppercase()];
+    let entry = match parts.next() {
+        Some(reg) => &opcode["reg"][reg],
+        None => opcode,
+    };
+    entry["flags-mask"].as_u64().unwrap_or(0xffff) as u16
+}
+
+fn run_case(case: &Value, flags_mask: u16) -> Result<(), String> {
+    let initial = &case["initial"];
+    let mut registers = RegisterFile::default();
+    set_registers(&mut registers, &initial["regs"]);
+    let mut expected = registers;
+    set_registers(&mut expected, &case["final"]["regs"]);
+
+    let mut memory = Memory::default();
+    for (address, value) in ram(&initial["ram"]) {
+        memory[address] = value;
+    }
+
+    let start = (registers.cs, registers.ip);
+    let mut io = IoBus::default();
+    loop {
+        let instruction = fetch(&memory, &registers);
+        let rep = instruction.prefixes.rep.is_some();
+        simulate(&mut registers, &mut memory, &mut io, instruction);
+        // A REP string instruction steps back onto itself until it is done.
+        if !rep || (registers.cs, registers.ip) != start {
+            break;
+        }
+    }
+
+    registers.flags &= flags_mask;
+    expected.flags &= flags_mask;
+    registers.interrupt_shadow = false;
+    if registers != expected {
+        return Err(format!("registers {registers:x?}, expected {expected:x?}"));
+    }
+    for (address, value) in ram(&case["final"]["ram"]) {
+        if memory[address] != value {
+            return Err(format!(
+                "memory at {address:05x} is {:02x}, expected {value:02x}",
+                memory[address]
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn set_registers(registers: &mut RegisterFile, regs: &Value) {
+    let Some(regs) = regs.as_object() else {
+        return;
+    };
+    for (name, value) in regs {
+        let value = value.as_u64().expect("register value") as u16;
+        let register = match name.as_str() {
+            "ax" => &mut registers.ax,
+            "bx" => &mut registers.bx,
+            "cx" => &mut registers.cx,
+            "dx" => &mut registers.dx,
+            "sp" => &mut registers.sp,
+            "bp" => &mut registers.bp,
+            "si" => &mut registers.si,
+            "di" => &mut registers.di,
+            "cs" => &mut registers.cs,
+            "ds" => &mut registers.ds,
+            "es" => &mut registers.es,
+            "ss" => &mut registers.ss,
+            "ip" => &mut registers.ip,
+            "flags" => &mut registers.flags,
+            _ => continue,
+        };
+        *register = value;
+    }
+}
+
+fn ram(ram: &Value) -> impl Iterator<Item = (usize, u8)> + '_ {
+    ram.as_array().into_iter().flatten().map(|pair| {
+        (
+            pair[0].as_u64().expect("address") as usize & 0xfffff,
+            pair[1].as_u64().expect("byte") as u8,
+        )
+    })
+}
+
+fn fetch(memory: &[u8], registers: &RegisterFile) -> Instruction {
+    let mut window = [0; 16];
+    for (i, byte) in window.iter_mut().enumerate() {
+        let offset = registers.ip.wrapping_add(i as u16) as usize;
+        *byte = memory[(((registers.cs as usize) << 4) + offset) & 0xfffff];
+    }
+    decode_instruction(&window)
+}